@@ -0,0 +1,66 @@
+use aoc2020::day4::fixtures::FixtureGenerator;
+use aoc2020::day4::{self, Passport};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use regex::Regex;
+
+/// The validators as they were before patterns were compiled once, with a
+/// `Regex::new` for every check.
+fn part2_recompiling(passports: &[Passport]) -> u64 {
+    let valid_date = |s: &str, lower: u32, upper: u32| {
+        let re = Regex::new(r"^\d{4}$").unwrap();
+        re.is_match(s) && (lower..=upper).contains(&s.parse().unwrap())
+    };
+    let valid_height = |s: &str| {
+        let re = Regex::new(r"^\d+(cm|in)$").unwrap();
+        if !re.is_match(s) {
+            return false;
+        }
+        let num: u32 = s[..s.len() - 2].parse().unwrap();
+        if s.ends_with("cm") {
+            (150..=193).contains(&num)
+        } else {
+            (59..=76).contains(&num)
+        }
+    };
+    let matches = |pattern: &str, s: &str| Regex::new(pattern).unwrap().is_match(s);
+    let valid = |p: &Passport| -> Option<bool> {
+        Some(
+            valid_date(p.get("byr")?, 1920, 2002)
+                && valid_date(p.get("iyr")?, 2010, 2020)
+                && valid_date(p.get("eyr")?, 2020, 2030)
+                && valid_height(p.get("hgt")?)
+                && matches(r"^\#([0-9a-f]{6})$", p.get("hcl")?)
+                && matches(r"^(amb|blu|brn|gry|grn|hzl|oth)$", p.get("ecl")?)
+                && matches(r"^([0-9]{9})$", p.get("pid")?),
+        )
+    };
+    passports.iter().filter(|p| valid(p) == Some(true)).count() as u64
+}
+
+fn validators(c: &mut Criterion) {
+    let batch = FixtureGenerator::new(0x2020).batch(100_000);
+    let passports = day4::generate(&batch.text());
+    assert_eq!(day4::part2(&passports), batch.strict_count());
+    // Recompiling takes most of a minute over the whole input, so the two
+    // are compared on the first 10k passports.
+    let first = &passports[..10_000];
+    assert_eq!(day4::part2(first), part2_recompiling(first));
+
+    let mut group = c.benchmark_group("day4 part2");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(first.len() as u64));
+    group.bench_function("compiled once, 10k passports", |b| {
+        b.iter(|| day4::part2(black_box(first)))
+    });
+    group.bench_function("recompiling, 10k passports", |b| {
+        b.iter(|| part2_recompiling(black_box(first)))
+    });
+    group.throughput(Throughput::Elements(passports.len() as u64));
+    group.bench_function("compiled once, 100k passports", |b| {
+        b.iter(|| day4::part2(black_box(&passports)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, validators);
+criterion_main!(benches);
@@ -0,0 +1,126 @@
+use aoc2020::day4::fixtures::Rng;
+use aoc2020::day6;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+/// Groups of one to six people, each answering a random handful of `a..=z`.
+fn generate_input(groups: usize) -> String {
+    let mut rng = Rng::new(0x2020);
+    let mut input = String::new();
+    for _ in 0..groups {
+        for _ in 0..rng.between(1, 6) {
+            for _ in 0..rng.between(1, 13) {
+                input.push((b'a' + rng.below(26) as u8) as char);
+            }
+            input.push('\n');
+        }
+        input.push('\n');
+    }
+    input
+}
+
+/// The day6 solution from before answers were kept as bitmasks, to measure
+/// against.
+mod hash_set {
+    use std::collections::HashSet;
+
+    #[derive(Debug, Default)]
+    pub struct Group {
+        answers: Vec<HashSet<char>>,
+    }
+
+    impl Group {
+        pub fn new() -> Self {
+            Default::default()
+        }
+
+        pub fn append(&mut self, answer: HashSet<char>) {
+            self.answers.push(answer);
+        }
+
+        // Count of any unique answer.
+        pub fn count_unique(&self) -> u16 {
+            let mut group = self.answers.first().unwrap().clone();
+            for a in &self.answers[1..] {
+                group.extend(a);
+            }
+            group.len() as u16
+        }
+
+        // Count of shared answers.
+        pub fn count_shared(&self) -> u16 {
+            let mut group = self.answers.first().unwrap().clone();
+            for a in &self.answers[1..] {
+                let g = group.intersection(a);
+                group = g.cloned().collect();
+            }
+            group.len() as u16
+        }
+    }
+
+    pub fn generate(input: &str) -> Vec<Group> {
+        let mut group = Group::new();
+        let mut output = vec![];
+        for s in input.lines() {
+            // Empty line means end of group.
+            if s.is_empty() {
+                output.push(group);
+                group = Group::new();
+            } else {
+                let mut individual = HashSet::new();
+                for c in s.chars() {
+                    individual.insert(c);
+                }
+                group.append(individual);
+            }
+        }
+        output.push(group);
+        output
+    }
+}
+
+/// Sums into a `u64`, as the solvers' `u16` would overflow on this input.
+fn total<G>(groups: &[G], count: impl Fn(&G) -> u16) -> u64 {
+    groups.iter().map(|g| u64::from(count(g))).sum()
+}
+
+fn answer_sets(c: &mut Criterion) {
+    let input = generate_input(100_000);
+    let bits = day6::generate(&input);
+    // The original generator ends each group on a blank line, so the
+    // input's trailing one leaves an empty group behind.
+    let mut hashed = hash_set::generate(&input);
+    hashed.pop();
+    assert_eq!(
+        total(&bits, day6::Group::count_unique),
+        total(&hashed, hash_set::Group::count_unique)
+    );
+    assert_eq!(
+        total(&bits, day6::Group::count_shared),
+        total(&hashed, hash_set::Group::count_shared)
+    );
+
+    let mut group = c.benchmark_group("day6 100k groups");
+    group.sample_size(20);
+    group.bench_function("generate hash set", |b| {
+        b.iter(|| hash_set::generate(black_box(&input)))
+    });
+    group.bench_function("generate bits", |b| {
+        b.iter(|| day6::generate(black_box(&input)))
+    });
+    group.bench_function("part1 hash set", |b| {
+        b.iter(|| total(black_box(&hashed), hash_set::Group::count_unique))
+    });
+    group.bench_function("part1 bits", |b| {
+        b.iter(|| total(black_box(&bits), day6::Group::count_unique))
+    });
+    group.bench_function("part2 hash set", |b| {
+        b.iter(|| total(black_box(&hashed), hash_set::Group::count_shared))
+    });
+    group.bench_function("part2 bits", |b| {
+        b.iter(|| total(black_box(&bits), day6::Group::count_shared))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, answer_sets);
+criterion_main!(benches);
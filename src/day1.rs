@@ -1,38 +1,171 @@
+use crate::parse_error::{column_of, ParseError};
 use itertools::Itertools;
-use std::collections::HashSet;
+use std::cmp::Ordering;
 
-#[aoc_generator(day1)]
-pub fn input_generator(input: &str) -> Vec<u32> {
+pub fn try_input_generator(input: &str) -> Result<Vec<u32>, ParseError> {
     input
         .lines()
-        .map(|l| {
-            let num = l.trim().parse().unwrap();
-            num
+        .enumerate()
+        .map(|(i, l)| {
+            let num = l.trim();
+            num.parse()
+                .map_err(|_| ParseError::unexpected(i + 1, column_of(l, num), num, "a number"))
         })
         .collect()
 }
 
-#[aoc(day1, part1)]
-pub fn adds_up(input: &[u32]) -> u32 {
-    // The set of 'a' for which '2020 - a' was already seen.
-    let mut seen = HashSet::new();
-    for k in input {
-        if seen.contains(k) {
-            return (2020 - k) * k;
+#[aoc_generator(day1)]
+pub fn input_generator(input: &str) -> Vec<u32> {
+    try_input_generator(input).unwrap_or_else(|e| panic!("{}", e))
+}
+
+fn k_sum_sorted(sorted: &[u32], k: usize, target: u32) -> Option<Vec<u32>> {
+    match k {
+        0 if target == 0 => Some(vec![]),
+        0 => None,
+        1 => sorted.binary_search(&target).ok().map(|_| vec![target]),
+        2 => {
+            if sorted.len() < 2 {
+                return None;
+            }
+            let (mut lo, mut hi) = (0, sorted.len() - 1);
+            while lo < hi {
+                let sum = u64::from(sorted[lo]) + u64::from(sorted[hi]);
+                match sum.cmp(&u64::from(target)) {
+                    Ordering::Less => lo += 1,
+                    Ordering::Greater => hi -= 1,
+                    Ordering::Equal => return Some(vec![sorted[lo], sorted[hi]]),
+                }
+            }
+            None
+        }
+        _ => {
+            for (i, x) in sorted.iter().enumerate() {
+                // Everything after `x` is at least `x`, so once k of them
+                // overshoot there is nothing left to find.
+                if u64::from(*x) * k as u64 > u64::from(target) {
+                    break;
+                }
+                if let Some(mut rest) = k_sum_sorted(&sorted[i + 1..], k - 1, target - x) {
+                    rest.insert(0, *x);
+                    return Some(rest);
+                }
+            }
+            None
+        }
+    }
+}
+
+/// Finds `k` entries of `input` that add up to `target`, in ascending order.
+///
+/// Works on a sorted copy of `input`: pairs are found with two pointers and
+/// every further entry adds a linear scan, so this is O(n^(k-1)) for k >= 2.
+pub fn find_k_sum(input: &[u32], k: usize, target: u32) -> Option<Vec<u32>> {
+    let mut sorted = input.to_vec();
+    sorted.sort_unstable();
+    k_sum_sorted(&sorted, k, target)
+}
+
+/// Every combination of `k` entries of `input` that adds up to `target`,
+/// each in ascending order. Entries that appear more than once in `input`
+/// can give the same values more than once.
+pub fn k_sums(input: &[u32], k: usize, target: u32) -> KSums {
+    let mut sorted = input.to_vec();
+    sorted.sort_unstable();
+    KSums {
+        sorted,
+        k,
+        target: u64::from(target),
+        chosen: vec![],
+        sum: 0,
+        next: 0,
+        done: false,
+    }
+}
+
+/// Iterator returned by `k_sums`, a depth first search over the sorted
+/// entries that skips any branch whose smallest possible sum is too big.
+pub struct KSums {
+    sorted: Vec<u32>,
+    k: usize,
+    target: u64,
+    chosen: Vec<usize>,
+    sum: u64,
+    next: usize,
+    done: bool,
+}
+
+impl KSums {
+    /// Drops the last chosen entry and moves on to the one after it, false
+    /// when there is nothing left to drop.
+    fn backtrack(&mut self) -> bool {
+        match self.chosen.pop() {
+            Some(last) => {
+                self.sum -= u64::from(self.sorted[last]);
+                self.next = last + 1;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Iterator for KSums {
+    type Item = Vec<u32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            if self.chosen.len() == self.k {
+                let found = self.sum == self.target;
+                let values = self.chosen.iter().map(|i| self.sorted[*i]).collect();
+                self.done = !self.backtrack();
+                if found {
+                    return Some(values);
+                }
+                continue;
+            }
+            let slots = (self.k - self.chosen.len()) as u64;
+            let fits = self
+                .sorted
+                .get(self.next)
+                .is_some_and(|x| self.sum + u64::from(*x) * slots <= self.target);
+            if fits {
+                self.chosen.push(self.next);
+                self.sum += u64::from(self.sorted[self.next]);
+                self.next += 1;
+            } else {
+                self.done = !self.backtrack();
+            }
         }
-        seen.insert(2020 - k);
+        None
     }
-    unreachable!();
+}
+
+/// The product of `values`, or `None` if it does not fit in a `u32`.
+pub fn checked_product(values: &[u32]) -> Option<u32> {
+    values
+        .iter()
+        .try_fold(1u32, |product, v| product.checked_mul(*v))
+}
+
+#[aoc(day1, part1)]
+pub fn adds_up(input: &[u32]) -> Option<u32> {
+    find_k_sum(input, 2, 2020).and_then(|entries| checked_product(&entries))
+}
+
+#[aoc(day1, part2)]
+pub fn part2(input: &[u32]) -> Option<u32> {
+    find_k_sum(input, 3, 2020).and_then(|entries| checked_product(&entries))
 }
 
 #[aoc(day1, part2, brute)]
-pub fn part2_brute(input: &[u32]) -> u32 {
+pub fn part2_brute(input: &[u32]) -> Option<u32> {
     for k in input.iter().combinations(3) {
-        if k[0] + k[1] + k[2] == 2020 {
-            return k[0] * k[1] * k[2];
+        if k.iter().map(|v| u64::from(**v)).sum::<u64>() == 2020 {
+            return checked_product(&[*k[0], *k[1], *k[2]]);
         }
     }
-    unreachable!();
+    None
 }
 
 #[cfg(test)]
@@ -43,9 +176,56 @@ mod tests {
         assert_eq!(input_generator("2\n30\n"), vec![2, 30]);
     }
 
+    #[test]
+    fn generator_error() {
+        assert_eq!(
+            try_input_generator("2\n 3o\n"),
+            Err(ParseError::unexpected(2, 2, "3o", "a number"))
+        );
+    }
+
     #[test]
     fn part1() {
-        assert_eq!(adds_up(&[2019, 88, 1]), 2019);
-        assert_eq!(adds_up(&[20, 88, 1, 2000]), 40_000);
+        assert_eq!(adds_up(&[2019, 88, 1]), Some(2019));
+        assert_eq!(adds_up(&[20, 88, 1, 2000]), Some(40_000));
+        assert_eq!(adds_up(&[20, 88, 1]), None);
+        assert_eq!(adds_up(&[3000, 1010, 4_000_000_000, 1010]), Some(1_020_100));
+    }
+
+    #[test]
+    fn part2_example() {
+        let input = [1721, 979, 366, 299, 675, 1456];
+        assert_eq!(part2(&input), Some(241_861_950));
+        assert_eq!(part2_brute(&input), Some(241_861_950));
+        assert_eq!(part2(&input[..3]), None);
+        assert_eq!(part2_brute(&input[..3]), None);
+    }
+
+    #[test]
+    fn k_sum() {
+        let input = [5, 1, 9, 3, 7, 4000];
+        assert_eq!(find_k_sum(&input, 0, 0), Some(vec![]));
+        assert_eq!(find_k_sum(&input, 1, 9), Some(vec![9]));
+        assert_eq!(find_k_sum(&input, 2, 12), Some(vec![3, 9]));
+        assert_eq!(find_k_sum(&input, 4, 22), Some(vec![1, 5, 7, 9]));
+        assert_eq!(find_k_sum(&input, 5, 4018), Some(vec![1, 3, 5, 9, 4000]));
+        assert_eq!(find_k_sum(&input, 6, 4025), Some(vec![1, 3, 5, 7, 9, 4000]));
+        assert_eq!(find_k_sum(&input, 6, 4024), None);
+        assert_eq!(find_k_sum(&input, 7, 4025), None);
+        assert_eq!(find_k_sum(&input, 2, 2), None);
+    }
+
+    #[test]
+    fn all_k_sums() {
+        let input = [5, 1, 9, 3, 7, 4, 6];
+        let sums: Vec<Vec<u32>> = k_sums(&input, 3, 15).collect();
+        assert_eq!(sums, vec![vec![1, 5, 9], vec![3, 5, 7], vec![4, 5, 6]]);
+    }
+
+    #[test]
+    fn product_overflow() {
+        assert_eq!(checked_product(&[]), Some(1));
+        assert_eq!(checked_product(&[65_536, 65_535]), Some(4_294_901_760));
+        assert_eq!(checked_product(&[65_536, 65_536]), None);
     }
 }
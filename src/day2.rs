@@ -1,74 +1,423 @@
+use crate::parse_error::{column_of, ParseError};
+use regex::Regex;
+use std::fmt;
+use std::iter;
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The `first-second letter:` part of a line. What the two numbers mean is
+/// up to the `PasswordPolicy` that checks it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule {
+    pub first: u32,
+    pub second: u32,
+    pub letter: char,
+}
+
+/// How the generator treats passwords, and so what a position counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextMode {
+    /// Non-ASCII rules and passwords are parse errors, positions are bytes.
+    AsciiOnly,
+    /// Rules and passwords are NFC-normalised, positions are grapheme
+    /// clusters so `e` followed by a combining accent is one position.
+    #[default]
+    Unicode,
+}
+
 #[derive(Debug)]
 pub struct Password {
-    rule: (u32, u32, char),
+    rule: Rule,
     value: String,
+    /// Where each grapheme of a non-ASCII `value` starts, followed by
+    /// `value.len()`. `None` for ASCII, where every byte is a position.
+    bounds: Option<Vec<usize>>,
+}
+
+impl Password {
+    /// NFC-normalises `value` and indexes its graphemes so that `at` is O(1).
+    pub fn new(rule: Rule, value: &str) -> Self {
+        let value: String = value.nfc().collect();
+        let bounds = if value.is_ascii() {
+            None
+        } else {
+            let starts = value.grapheme_indices(true).map(|(i, _)| i);
+            Some(starts.chain(iter::once(value.len())).collect())
+        };
+        Password {
+            rule: Rule {
+                letter: normalize_letter(rule.letter),
+                ..rule
+            },
+            value,
+            bounds,
+        }
+    }
+
+    pub fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Number of positions, bytes for ASCII and graphemes otherwise.
+    pub fn len(&self) -> usize {
+        match &self.bounds {
+            None => self.value.len(),
+            Some(bounds) => bounds.len() - 1,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    /// The grapheme at a 1-based `position`, if the password is that long.
+    pub fn at(&self, position: u32) -> Option<&str> {
+        let i = (position as usize).checked_sub(1)?;
+        match &self.bounds {
+            None => self.value.get(i..i + 1),
+            Some(bounds) => Some(&self.value[*bounds.get(i)?..*bounds.get(i + 1)?]),
+        }
+    }
+
+    /// Every position's grapheme, in order.
+    pub fn graphemes(&self) -> impl Iterator<Item = &str> {
+        (1..=self.len() as u32).map(move |p| self.at(p).unwrap())
+    }
+
+    pub fn check(&self, policy: &impl PasswordPolicy) -> Verdict {
+        policy.check(self)
+    }
+}
+
+/// The NFC form of `letter`, when that is still a single char.
+fn normalize_letter(letter: char) -> char {
+    let mut nfc = iter::once(letter).nfc();
+    match (nfc.next(), nfc.next()) {
+        (Some(c), None) => c,
+        _ => letter,
+    }
+}
+
+fn is_letter(grapheme: &str, letter: char) -> bool {
+    let mut chars = grapheme.chars();
+    chars.next() == Some(letter) && chars.next().is_none()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Valid,
+    Invalid(Reason),
+}
+
+impl Verdict {
+    pub fn is_valid(&self) -> bool {
+        *self == Verdict::Valid
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Verdict::Valid => write!(f, "valid"),
+            Verdict::Invalid(reason) => write!(f, "invalid: {}", reason),
+        }
+    }
+}
+
+/// Why a password broke its policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    WrongCount {
+        letter: char,
+        count: u32,
+        min: u32,
+        max: u32,
+    },
+    /// Positions are 1-based, `len` is in graphemes (bytes for ASCII).
+    PositionOutOfRange {
+        position: u32,
+        len: usize,
+    },
+    NotAtExactlyOne {
+        letter: char,
+        positions: (u32, u32),
+        found: u32,
+    },
+    NotAtEither {
+        letter: char,
+        positions: (u32, u32),
+    },
+    FoundAt {
+        letter: char,
+        position: u32,
+    },
+    NoMatch {
+        pattern: String,
+    },
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::WrongCount {
+                letter,
+                count,
+                min,
+                max,
+            } => write!(
+                f,
+                "{:?} appears {} times, expected {} to {}",
+                letter, count, min, max
+            ),
+            Reason::PositionOutOfRange { position, len } => write!(
+                f,
+                "position {} is outside a password of length {}",
+                position, len
+            ),
+            Reason::NotAtExactlyOne {
+                letter,
+                positions,
+                found,
+            } => write!(
+                f,
+                "{:?} is at {} of positions {} and {}, expected exactly one",
+                letter, found, positions.0, positions.1
+            ),
+            Reason::NotAtEither { letter, positions } => write!(
+                f,
+                "{:?} is at neither position {} nor {}",
+                letter, positions.0, positions.1
+            ),
+            Reason::FoundAt { letter, position } => {
+                write!(f, "{:?} is at forbidden position {}", letter, position)
+            }
+            Reason::NoMatch { pattern } => write!(f, "does not match /{}/", pattern),
+        }
+    }
+}
+
+/// One way of reading a `Rule`.
+pub trait PasswordPolicy {
+    fn check(&self, password: &Password) -> Verdict;
+}
+
+/// The letter appears between `first` and `second` times, inclusive.
+pub struct CountRange;
+
+/// The letter is at exactly one of the 1-based positions `first` and `second`.
+pub struct ExactlyOnePosition;
+
+/// The letter is at one or both of the 1-based positions `first` and `second`.
+pub struct AtLeastOnePosition;
+
+/// The letter is at neither of the 1-based positions `first` and `second`.
+pub struct NoneOfPositions;
+
+/// The whole password matches a regex, the rule is ignored.
+pub struct MatchesRegex(pub Regex);
+
+fn verdict(valid: bool, reason: impl FnOnce() -> Reason) -> Verdict {
+    if valid {
+        Verdict::Valid
+    } else {
+        Verdict::Invalid(reason())
+    }
+}
+
+/// Whether the letter is at each of the rule's two positions, both of which
+/// must be inside the password.
+fn at_positions(password: &Password) -> Result<(bool, bool), Reason> {
+    let rule = password.rule();
+    let at = |position: u32| {
+        password
+            .at(position)
+            .map(|g| is_letter(g, rule.letter))
+            .ok_or(Reason::PositionOutOfRange {
+                position,
+                len: password.len(),
+            })
+    };
+    Ok((at(rule.first)?, at(rule.second)?))
+}
+
+impl PasswordPolicy for CountRange {
+    fn check(&self, password: &Password) -> Verdict {
+        let rule = password.rule();
+        let count = password
+            .graphemes()
+            .filter(|g| is_letter(g, rule.letter))
+            .count() as u32;
+        verdict((rule.first..=rule.second).contains(&count), || {
+            Reason::WrongCount {
+                letter: rule.letter,
+                count,
+                min: rule.first,
+                max: rule.second,
+            }
+        })
+    }
+}
+
+impl PasswordPolicy for ExactlyOnePosition {
+    fn check(&self, password: &Password) -> Verdict {
+        let rule = password.rule();
+        match at_positions(password) {
+            Ok((a, b)) => verdict(a ^ b, || Reason::NotAtExactlyOne {
+                letter: rule.letter,
+                positions: (rule.first, rule.second),
+                found: a as u32 + b as u32,
+            }),
+            Err(reason) => Verdict::Invalid(reason),
+        }
+    }
+}
+
+impl PasswordPolicy for AtLeastOnePosition {
+    fn check(&self, password: &Password) -> Verdict {
+        let rule = password.rule();
+        match at_positions(password) {
+            Ok((a, b)) => verdict(a || b, || Reason::NotAtEither {
+                letter: rule.letter,
+                positions: (rule.first, rule.second),
+            }),
+            Err(reason) => Verdict::Invalid(reason),
+        }
+    }
+}
+
+impl PasswordPolicy for NoneOfPositions {
+    fn check(&self, password: &Password) -> Verdict {
+        let rule = password.rule();
+        match at_positions(password) {
+            Ok((a, b)) => verdict(!a && !b, || Reason::FoundAt {
+                letter: rule.letter,
+                position: if a { rule.first } else { rule.second },
+            }),
+            Err(reason) => Verdict::Invalid(reason),
+        }
+    }
+}
+
+impl PasswordPolicy for MatchesRegex {
+    fn check(&self, password: &Password) -> Verdict {
+        verdict(self.0.is_match(password.value()), || Reason::NoMatch {
+            pattern: self.0.as_str().into(),
+        })
+    }
+}
+
+fn parse_number(line_no: usize, line: &str, text: &str) -> Result<u32, ParseError> {
+    text.parse()
+        .map_err(|_| ParseError::unexpected(line_no, column_of(line, text), text, "a number"))
+}
+
+/// Rejects `text` at its first non-ASCII char.
+fn check_ascii(line_no: usize, line: &str, text: &str, expected: &str) -> Result<(), ParseError> {
+    match text.char_indices().find(|(_, c)| !c.is_ascii()) {
+        Some((i, _)) => Err(ParseError::unexpected(
+            line_no,
+            column_of(line, &text[i..]),
+            text,
+            expected,
+        )),
+        None => Ok(()),
+    }
+}
+
+fn parse_password(line_no: usize, l: &str, mode: TextMode) -> Result<Password, ParseError> {
+    let line = l.trim();
+    let at = column_of(l, line);
+    let end = || at + line.chars().count();
+    let mut parts = line.split(' ');
+
+    let range = parts.next().unwrap();
+    let (lower, upper) = range.split_once('-').ok_or_else(|| {
+        ParseError::unexpected(line_no, column_of(l, range), range, "a `min-max` range")
+    })?;
+    let lower = parse_number(line_no, l, lower)?;
+    let upper = parse_number(line_no, l, upper)?;
+
+    let letter = parts
+        .next()
+        .ok_or_else(|| ParseError::missing(line_no, end(), "a letter followed by `:`"))?;
+    let mut chars = letter.chars();
+    let c = match (chars.next(), chars.next(), chars.next()) {
+        (Some(c), Some(':'), None) => c,
+        _ => {
+            return Err(ParseError::unexpected(
+                line_no,
+                column_of(l, letter),
+                letter,
+                "a letter followed by `:`",
+            ))
+        }
+    };
+
+    let value = parts
+        .next()
+        .ok_or_else(|| ParseError::missing(line_no, end(), "a password"))?;
+    if let Some(extra) = parts.next() {
+        return Err(ParseError::unexpected(
+            line_no,
+            column_of(l, extra),
+            extra,
+            "end of line",
+        ));
+    }
+    if mode == TextMode::AsciiOnly {
+        check_ascii(line_no, l, letter, "an ASCII letter")?;
+        check_ascii(line_no, l, value, "an ASCII password")?;
+    }
+    let rule = Rule {
+        first: lower,
+        second: upper,
+        letter: c,
+    };
+    Ok(Password::new(rule, value))
+}
+
+pub fn try_input_generator_with(input: &str, mode: TextMode) -> Result<Vec<Password>, ParseError> {
+    input
+        .lines()
+        .enumerate()
+        .map(|(i, l)| parse_password(i + 1, l, mode))
+        .collect()
+}
+
+pub fn try_input_generator(input: &str) -> Result<Vec<Password>, ParseError> {
+    try_input_generator_with(input, TextMode::default())
 }
 
 #[aoc_generator(day2)]
 pub fn input_generator(input: &str) -> Vec<Password> {
+    try_input_generator(input).unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn count_valid_with(input: &[Password], policy: &impl PasswordPolicy) -> u32 {
     input
-        .lines()
-        .map(|l| {
-            let parts: Vec<&str> = l.trim().split(' ').collect();
-            let range: Vec<&str> = parts[0].split('-').collect();
-            let c = parts[1].chars().next().unwrap();
-            Password {
-                rule: (range[0].parse().unwrap(), range[1].parse().unwrap(), c),
-                value: parts[2].into(),
+        .iter()
+        .filter(|p| {
+            let verdict = p.check(policy);
+            if !verdict.is_valid() {
+                aoc_debug!("{:?} with {:?}: {}", p.value(), p.rule(), verdict);
             }
+            verdict.is_valid()
         })
-        .collect()
+        .count() as u32
 }
 
 #[aoc(day2, part1)]
 pub fn count_valid(input: &[Password]) -> u32 {
-    let mut valid_count = 0u32;
-    for p in input {
-        let mut valid = false;
-        let mut count = 0;
-        let (min, max, target) = p.rule;
-        for c in p.value.chars() {
-            if c == target {
-                count += 1;
-                if count >= min {
-                    valid = true;
-                }
-                if count > max {
-                    valid = false;
-                    break;
-                }
-            }
-        }
-        if valid {
-            valid_count += 1;
-        } else {
-            println!("Invalid password {:?}", p);
-        }
-    }
-    valid_count
+    count_valid_with(input, &CountRange)
 }
 
 #[aoc(day2, part2)]
 pub fn count_valid2(input: &[Password]) -> u32 {
-    let mut valid_count = 0u32;
-    for p in input {
-        let (p0, p1, target) = p.rule;
-        let p0 = (p0 - 1) as usize;
-        let p1 = (p1 - 1) as usize;
-
-        if p.value.len() <= p0 || p.value.len() <= p1 {
-            println!("Short password {:?}", p);
-            continue;
-        }
-        let c0 = p.value.chars().nth(p0).unwrap();
-        let c1 = p.value.chars().nth(p1).unwrap();
-        if (c0 == target) ^ (c1 == target) {
-            valid_count += 1;
-        } else {
-            println!("Invalid password {:?}", p);
-        }
-    }
-    valid_count
+    count_valid_with(input, &ExactlyOnePosition)
 }
 
 #[cfg(test)]
@@ -81,16 +430,174 @@ mod tests {
             2-9 c: ccccccccc"#;
         let v = input_generator(input);
         let p0 = &v[0];
-        assert_eq!(p0.rule, (1, 3, 'a'));
+        assert_eq!(
+            p0.rule,
+            Rule {
+                first: 1,
+                second: 3,
+                letter: 'a'
+            }
+        );
         assert_eq!(p0.value, "abcde");
         let p1 = &v[1];
-        assert_eq!(p1.rule, (1, 3, 'b'));
+        assert_eq!(
+            p1.rule,
+            Rule {
+                first: 1,
+                second: 3,
+                letter: 'b'
+            }
+        );
         assert_eq!(p1.value, "cdefg");
         let p2 = &v[2];
-        assert_eq!(p2.rule, (2, 9, 'c'));
+        assert_eq!(
+            p2.rule,
+            Rule {
+                first: 2,
+                second: 9,
+                letter: 'c'
+            }
+        );
         assert_eq!(p2.value, "ccccccccc");
     }
 
     #[test]
-    fn part1() {}
+    fn generator_errors() {
+        assert_eq!(
+            try_input_generator("1-3 a: abcde\n  1+3 b: cdefg").unwrap_err(),
+            ParseError::unexpected(2, 3, "1+3", "a `min-max` range")
+        );
+        assert_eq!(
+            try_input_generator("1-x a: abcde").unwrap_err(),
+            ParseError::unexpected(1, 3, "x", "a number")
+        );
+        assert_eq!(
+            try_input_generator("1-3 ab: abcde").unwrap_err(),
+            ParseError::unexpected(1, 5, "ab:", "a letter followed by `:`")
+        );
+        assert_eq!(
+            try_input_generator("1-3 a:").unwrap_err(),
+            ParseError::missing(1, 7, "a password")
+        );
+        assert_eq!(
+            try_input_generator("1-3 a: abc de").unwrap_err(),
+            ParseError::unexpected(1, 12, "de", "end of line")
+        );
+    }
+
+    const EXAMPLE: &str = "1-3 a: abcde\n1-3 b: cdefg\n2-9 c: ccccccccc";
+
+    #[test]
+    fn part1() {
+        let v = input_generator(EXAMPLE);
+        assert_eq!(count_valid(&v), 2);
+        assert_eq!(
+            v[1].check(&CountRange),
+            Verdict::Invalid(Reason::WrongCount {
+                letter: 'b',
+                count: 0,
+                min: 1,
+                max: 3
+            })
+        );
+    }
+
+    #[test]
+    fn part2() {
+        let v = input_generator(EXAMPLE);
+        assert_eq!(count_valid2(&v), 1);
+        assert_eq!(
+            v[2].check(&ExactlyOnePosition),
+            Verdict::Invalid(Reason::NotAtExactlyOne {
+                letter: 'c',
+                positions: (2, 9),
+                found: 2
+            })
+        );
+    }
+
+    #[test]
+    fn other_policies() {
+        let v = input_generator(EXAMPLE);
+        assert_eq!(count_valid_with(&v, &AtLeastOnePosition), 2);
+        assert_eq!(count_valid_with(&v, &NoneOfPositions), 1);
+        assert_eq!(
+            v[0].check(&NoneOfPositions),
+            Verdict::Invalid(Reason::FoundAt {
+                letter: 'a',
+                position: 1
+            })
+        );
+        let vowels = MatchesRegex(Regex::new("[aeiou]").unwrap());
+        assert_eq!(count_valid_with(&v, &vowels), 2);
+        assert_eq!(
+            v[2].check(&vowels).to_string(),
+            "invalid: does not match /[aeiou]/"
+        );
+    }
+
+    #[test]
+    fn positions_out_of_range() {
+        let v = input_generator("0-1 a: abc\n1-4 a: abc");
+        let out_of_range =
+            |position| Verdict::Invalid(Reason::PositionOutOfRange { position, len: 3 });
+        assert_eq!(v[0].check(&ExactlyOnePosition), out_of_range(0));
+        assert_eq!(v[1].check(&AtLeastOnePosition), out_of_range(4));
+        assert_eq!(
+            out_of_range(4).to_string(),
+            "invalid: position 4 is outside a password of length 3"
+        );
+    }
+
+    #[test]
+    fn unicode_passwords() {
+        // "é" precomposed, then as `e` plus a combining acute accent.
+        let input = "1-2 \u{e9}: \u{e9}t\u{e9}\n1-3 \u{e9}: cafe\u{301}!";
+        let v = input_generator(input);
+        assert_eq!(v[0].len(), 3);
+        assert_eq!(v[1].value(), "caf\u{e9}!");
+        assert_eq!(v[1].len(), 5);
+        assert_eq!(v[1].at(4), Some("\u{e9}"));
+        assert_eq!(v[1].at(6), None);
+        assert_eq!(count_valid(&v), 2);
+        assert!(!v[0].check(&NoneOfPositions).is_valid());
+        assert_eq!(
+            v[1].check(&ExactlyOnePosition),
+            Verdict::Invalid(Reason::NotAtExactlyOne {
+                letter: '\u{e9}',
+                positions: (1, 3),
+                found: 0
+            })
+        );
+
+        // A flag is two chars but one grapheme.
+        let v = input_generator("1-3 x: \u{1f1eb}\u{1f1f7}x\n3-4 x: \u{1f1eb}\u{1f1f7}x");
+        assert_eq!(v[0].len(), 2);
+        assert_eq!(count_valid2(&v), 0);
+        assert_eq!(
+            v[1].check(&AtLeastOnePosition),
+            Verdict::Invalid(Reason::PositionOutOfRange {
+                position: 3,
+                len: 2
+            })
+        );
+    }
+
+    #[test]
+    fn ascii_only_mode() {
+        let input = "1-3 a: abcde";
+        assert_eq!(
+            try_input_generator_with(input, TextMode::AsciiOnly).unwrap()[0].len(),
+            5
+        );
+        assert_eq!(
+            try_input_generator_with("1-3 a: ab\u{e9}cd", TextMode::AsciiOnly).unwrap_err(),
+            ParseError::unexpected(1, 10, "ab\u{e9}cd", "an ASCII password")
+        );
+        assert_eq!(
+            try_input_generator_with("1-3 \u{e9}: abc", TextMode::AsciiOnly).unwrap_err(),
+            ParseError::unexpected(1, 5, "\u{e9}:", "an ASCII letter")
+        );
+        assert!(try_input_generator_with("1-3 \u{e9}: abc", TextMode::Unicode).is_ok());
+    }
 }
@@ -1,4 +1,10 @@
-use std::ops::Index;
+use crate::grid::{Edges, Grid};
+use crate::parse_error::ParseError;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io;
+use std::ops::RangeInclusive;
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum Tile {
@@ -6,83 +12,253 @@ pub enum Tile {
     Snow,
 }
 
-pub struct Map {
-    cells: Vec<Tile>,
-    width: usize,
-    height: usize,
+impl TryFrom<char> for Tile {
+    type Error = &'static str;
+    fn try_from(c: char) -> Result<Self, Self::Error> {
+        match c {
+            '.' => Ok(Tile::Snow),
+            '#' => Ok(Tile::Tree),
+            _ => Err("`.` or `#`"),
+        }
+    }
 }
 
-impl Index<(usize, usize)> for Map {
-    type Output = Tile;
-    fn index(&self, tuple: (usize, usize)) -> &Self::Output {
-        let (x, y) = tuple;
-        let x = x % self.width;
-        let index = x + y * self.width;
-        &self.cells[index]
-    }
+/// The slope repeats to the right forever. The generator gives the grid
+/// `Edges::WrapX`, but the slope functions wrap `x` themselves, so they
+/// also work on a map parsed some other way.
+pub type Map = Grid<Tile>;
+
+pub fn try_input_generator(input: &str) -> Result<Map, ParseError> {
+    Ok(input.parse::<Map>()?.with_edges(Edges::WrapX))
 }
 
 #[aoc_generator(day3)]
 pub fn input_generator(input: &str) -> Map {
-    let lines: Vec<&str> = input.lines().collect();
-    let height = lines.len();
-    let mut width = 0usize;
-    let mut tiles = vec![];
-    for l in lines {
-        let line = l.trim();
-        if width == 0 {
-            width = line.len();
+    try_input_generator(input).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// One toboggan step: `dx` across, negative going left, then `dy` down.
+/// `(2, 4)` is the same gradient as `(1, 2)` but lands on every fourth row
+/// rather than every second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slope {
+    pub dx: isize,
+    pub dy: usize,
+}
+
+impl From<(isize, usize)> for Slope {
+    fn from((dx, dy): (isize, usize)) -> Self {
+        Slope { dx, dy }
+    }
+}
+
+/// The positions reached stepping by `slope` from the top left, until the
+/// bottom is passed. `x` is not wrapped, so it counts repeats of the map to
+/// the right, or to the left when negative. Panics if the slope doesn't go
+/// down, since it would never reach the bottom.
+pub fn slope_steps(map: &Map, slope: impl Into<Slope>) -> impl Iterator<Item = (isize, usize)> {
+    let slope = slope.into();
+    assert!(slope.dy > 0, "{:?} never reaches the bottom", slope);
+    let height = map.height();
+    (0..)
+        .map(move |k: usize| (k as isize * slope.dx, k * slope.dy))
+        .take_while(move |(_, y)| *y < height)
+}
+
+/// The cells `slope_steps` lands on, wrapped into the map whatever its
+/// edges. None if the map has no columns.
+pub fn slope_path(map: &Map, slope: impl Into<Slope>) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let slope = slope.into();
+    let width = map.width() as isize;
+    slope_steps(map, slope)
+        .filter(move |_| width > 0)
+        .map(move |(x, y)| (x.rem_euclid(width) as usize, y))
+}
+
+pub fn count_trees_on_slope(map: &Map, slope: impl Into<Slope>) -> u32 {
+    let slope = slope.into();
+    let trees = slope_path(map, slope)
+        .filter(|pos| map[*pos] == Tile::Tree)
+        .count() as u32;
+    if aoc_log_enabled!(Trace) {
+        aoc_trace!("slope {:?}:\n{}", slope, Renderer::new(map).path(slope));
+    }
+    aoc_debug!("slope: {:?} trees: {:?}", slope, trees);
+    trees
+}
+
+/// The slope with `dx` and `dy` in the given ranges that hits the fewest
+/// trees, and how many it hits. Ties go to the smallest `dx`, then `dy`.
+/// Slopes with `dy == 0` are skipped.
+pub fn best_slope(
+    map: &Map,
+    dx: RangeInclusive<isize>,
+    dy: RangeInclusive<usize>,
+) -> Option<(Slope, u32)> {
+    dx.flat_map(|dx| dy.clone().map(move |dy| Slope { dx, dy }))
+        .filter(|slope| slope.dy > 0)
+        .map(|slope| (slope, count_trees_on_slope(map, slope)))
+        .min_by_key(|(_, trees)| *trees)
+}
+
+/// Glyphs for a path stepping on a tree and on snow, one pair per path in
+/// the order they were added.
+const PATH_GLYPHS: [(char, char); 5] = [('X', 'O'), ('A', 'a'), ('B', 'b'), ('C', 'c'), ('D', 'd')];
+
+/// ANSI foreground colours, one per path, used instead of the distinct glyphs.
+const PATH_COLOURS: [u8; 5] = [31, 32, 33, 34, 35];
+
+/// Draws a map with slope paths overlaid, `#` and `.` for untouched trees
+/// and snow. Where paths cross, the one added first is drawn.
+pub struct Renderer<'a> {
+    map: &'a Map,
+    visits: HashMap<(usize, usize), usize>,
+    paths: usize,
+    ansi: bool,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(map: &'a Map) -> Self {
+        Renderer {
+            map,
+            visits: HashMap::new(),
+            paths: 0,
+            ansi: false,
         }
-        for c in line.chars() {
-            let tile = match c {
-                '.' => Tile::Snow,
-                '#' => Tile::Tree,
-                _ => panic!("Invalid tile"),
-            };
-            tiles.push(tile);
+    }
+
+    pub fn path(mut self, slope: impl Into<Slope>) -> Self {
+        for pos in slope_path(self.map, slope) {
+            self.visits.entry(pos).or_insert(self.paths);
         }
+        self.paths += 1;
+        self
     }
-    Map {
-        cells: tiles,
-        width,
-        height,
+
+    /// Colour each path with ANSI escapes, keeping `X`/`O` as the glyphs.
+    pub fn ansi(mut self, ansi: bool) -> Self {
+        self.ansi = ansi;
+        self
+    }
+
+    pub fn write_to(&self, out: &mut impl io::Write) -> io::Result<()> {
+        write!(out, "{}", self)
     }
 }
 
-pub fn count_trees_on_slope(map: &Map, slope: (usize, usize)) -> u32 {
-    let mut loc = (0, 0);
-    let mut trees = 0u32;
-    for y in 0..map.height {
-        let mut tiles = vec![];
-        for x in 0..map.width {
-            let is_target = (loc.0 % map.width, loc.1) == (x, y);
-            let e = match map[(x, y)] {
-                Tile::Tree => {
-                    if is_target {
-                        trees += 1;
-                        "X"
-                    } else {
-                        "#"
+impl fmt::Display for Renderer<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (y, row) in self.map.rows().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                let tree = *tile == Tile::Tree;
+                match self.visits.get(&(x, y)) {
+                    None => write!(f, "{}", if tree { '#' } else { '.' })?,
+                    Some(&path) if self.ansi => {
+                        let colour = PATH_COLOURS[path % PATH_COLOURS.len()];
+                        let glyph = if tree { 'X' } else { 'O' };
+                        write!(f, "\x1b[{}m{}\x1b[0m", colour, glyph)?
                     }
-                }
-                _ => {
-                    if is_target {
-                        "O"
-                    } else {
-                        "."
+                    Some(&path) => {
+                        let (hit, miss) = PATH_GLYPHS[path % PATH_GLYPHS.len()];
+                        write!(f, "{}", if tree { hit } else { miss })?
                     }
                 }
-            };
-            tiles.push(e);
-            if is_target {
-                loc.0 += slope.0;
-                loc.1 += slope.1;
             }
+            writeln!(f)?;
         }
-        println!("{}", tiles.join(""))
+        Ok(())
+    }
+}
+
+const SNOW_RGB: [u8; 3] = [235, 240, 250];
+const TREE_RGB: [u8; 3] = [30, 110, 40];
+
+/// Per path, in the order given: the colour of a visited snow cell and of a
+/// collision with a tree.
+const PATH_RGB: [([u8; 3], [u8; 3]); 5] = [
+    ([120, 170, 255], [220, 30, 30]),
+    ([255, 200, 90], [160, 60, 0]),
+    ([190, 130, 255], [110, 0, 160]),
+    ([120, 230, 200], [0, 120, 110]),
+    ([255, 150, 200], [180, 0, 90]),
+];
+
+/// An RGB picture, row by row from the top left.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<[u8; 3]>,
+}
+
+impl Image {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        self.pixels[x + y * self.width]
+    }
+
+    /// Writes a binary (P6) PPM.
+    pub fn write_ppm(&self, out: &mut impl io::Write) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", self.width, self.height)?;
+        out.write_all(&self.pixels.concat())
+    }
+
+    #[cfg(feature = "png")]
+    pub fn write_png(&self, out: impl io::Write) -> Result<(), png::EncodingError> {
+        let mut encoder = png::Encoder::new(out, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()?
+            .write_image_data(&self.pixels.concat())
+    }
+}
+
+/// Draws `map` with the path of each slope, each cell a `scale` pixel
+/// square. The map is repeated sideways as many whole times as the widest
+/// path needs, to the left too for negative `dx`. Where paths cross, the one
+/// listed first is drawn.
+pub fn render_image(map: &Map, slopes: &[Slope], scale: usize) -> Image {
+    assert!(scale > 0, "cells need at least one pixel");
+    let mut visits = HashMap::new();
+    for (i, slope) in slopes.iter().enumerate() {
+        for pos in slope_steps(map, *slope) {
+            visits.entry(pos).or_insert(i);
+        }
+    }
+    let map_width = map.width().max(1) as isize;
+    let xs = visits.keys().map(|(x, _)| *x);
+    let first = xs.clone().min().unwrap_or(0).min(0).div_euclid(map_width);
+    let last = xs.max().unwrap_or(0).div_euclid(map_width);
+    let (start, cells) = (first * map_width, (last - first + 1) * map_width);
+
+    let (width, height) = (cells as usize * scale, map.height() * scale);
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for col in 0..width {
+            let x = start + (col / scale) as isize;
+            let pos = (x.rem_euclid(map_width) as usize, y / scale);
+            let tree = map.get(pos) == Some(&Tile::Tree);
+            pixels.push(match visits.get(&(x, y / scale)) {
+                Some(&i) if tree => PATH_RGB[i % PATH_RGB.len()].1,
+                Some(&i) => PATH_RGB[i % PATH_RGB.len()].0,
+                None if tree => TREE_RGB,
+                None => SNOW_RGB,
+            });
+        }
+    }
+    Image {
+        width,
+        height,
+        pixels,
     }
-    println!("slope: {:?} trees: {:?}", slope, trees);
-    trees
 }
 
 #[aoc(day3, part1)]
@@ -96,7 +272,7 @@ pub fn part2(map: &Map) -> u64 {
         .iter()
         .map(|s| count_trees_on_slope(map, *s).into())
         .collect();
-    println!("slopes: {:?}", slopes);
+    aoc_debug!("slopes: {:?}", slopes);
     slopes.iter().product()
 }
 
@@ -158,5 +334,174 @@ mod tests {
     }
 
     #[test]
-    fn part1() {}
+    fn generator_errors() {
+        assert_eq!(
+            try_input_generator("..#\n#x.").unwrap_err(),
+            ParseError::unexpected(2, 2, "x", "`.` or `#`")
+        );
+        assert_eq!(
+            try_input_generator("..#\n#...#").unwrap_err(),
+            ParseError::unexpected(2, 4, ".#", "end of row")
+        );
+        assert_eq!(
+            try_input_generator("..#\n#.").unwrap_err(),
+            ParseError::missing(2, 3, "a row of 3 tiles")
+        );
+    }
+
+    const EXAMPLE: &str = indoc::indoc! {"
+        ..##.......
+        #...#...#..
+        .#....#..#.
+        ..#.#...#.#
+        .#...##..#.
+        ..#.##.....
+        .#.#.#....#
+        .#........#
+        #.##...#...
+        #...##....#
+        .#..#...#.#
+    "};
+
+    #[test]
+    fn part1() {
+        assert_eq!(super::part1(&input_generator(EXAMPLE)), 7);
+    }
+
+    #[test]
+    fn slope_paths() {
+        let map = input_generator(EXAMPLE);
+        let path: Vec<_> = slope_path(&map, (3, 1)).take(5).collect();
+        assert_eq!(path, [(0, 0), (3, 1), (6, 2), (9, 3), (1, 4)]);
+        assert_eq!(slope_path(&map, (1, 2)).count(), 6);
+        assert_eq!(slope_path(&map, (1, 2)).last(), Some((5, 10)));
+    }
+
+    #[test]
+    fn bounded_maps_still_wrap() {
+        let bounded: Map = EXAMPLE.parse().unwrap();
+        let wrapped = input_generator(EXAMPLE);
+        for slope in [(3, 1), (-5, 1), (1, 2), (12, 1)] {
+            assert_eq!(
+                count_trees_on_slope(&bounded, slope),
+                count_trees_on_slope(&wrapped, slope),
+                "{:?}",
+                slope
+            );
+        }
+        assert_eq!(count_trees_on_slope(&bounded, (3, 1)), 7);
+        assert_eq!(
+            render_image(&bounded, &[(3, 1).into()], 1),
+            render_image(&wrapped, &[(3, 1).into()], 1)
+        );
+    }
+
+    #[test]
+    fn any_slope() {
+        let map = input_generator(EXAMPLE);
+        let down: Vec<_> = slope_path(&map, (0, 3)).collect();
+        assert_eq!(down, [(0, 0), (0, 3), (0, 6), (0, 9)]);
+        let left: Vec<_> = slope_path(&map, (-3, 1)).take(3).collect();
+        assert_eq!(left, [(0, 0), (8, 1), (5, 2)]);
+        assert_eq!(count_trees_on_slope(&map, (-3, 1)), 3);
+        assert_eq!(count_trees_on_slope(&map, (2, 4)), 0);
+        assert_eq!(
+            count_trees_on_slope(&map, Slope { dx: 0, dy: 1 }),
+            count_trees_on_slope(&map, (11, 1))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "never reaches the bottom")]
+    fn flat_slope_should_panic() {
+        let _ = slope_path(&input_generator(EXAMPLE), (1, 0));
+    }
+
+    #[test]
+    fn best_slopes() {
+        let map = input_generator(EXAMPLE);
+        assert_eq!(
+            best_slope(&map, 1..=7, 1..=1),
+            Some((Slope { dx: 2, dy: 1 }, 1))
+        );
+        let (slope, trees) = best_slope(&map, -3..=3, 0..=4).unwrap();
+        assert_eq!(trees, 0);
+        assert_eq!(count_trees_on_slope(&map, slope), 0);
+        assert_eq!(best_slope(&map, 1..=3, 0..=0), None);
+    }
+
+    #[test]
+    fn steps_are_unwrapped() {
+        let map = input_generator(EXAMPLE);
+        let steps: Vec<_> = slope_steps(&map, (-3, 1)).take(3).collect();
+        assert_eq!(steps, [(0, 0), (-3, 1), (-6, 2)]);
+        assert_eq!(slope_steps(&map, (3, 1)).last(), Some((30, 10)));
+    }
+
+    #[test]
+    fn image() {
+        let map = input_generator("..#\n#..\n.#.");
+        let (path, hit) = PATH_RGB[0];
+
+        let image = render_image(&map, &[Slope { dx: 1, dy: 1 }], 2);
+        assert_eq!((image.width(), image.height()), (6, 6));
+        assert_eq!(image.pixel(1, 1), path);
+        assert_eq!(image.pixel(4, 0), TREE_RGB);
+        assert_eq!(image.pixel(2, 0), SNOW_RGB);
+
+        let slopes = [Slope { dx: 2, dy: 1 }, Slope { dx: -1, dy: 1 }];
+        let image = render_image(&map, &slopes, 1);
+        assert_eq!((image.width(), image.height()), (9, 3));
+        // Columns start one copy to the left, at x = -3.
+        assert_eq!(image.pixel(3, 0), path);
+        assert_eq!(image.pixel(5, 1), path);
+        assert_eq!(image.pixel(7, 2), hit);
+        assert_eq!(image.pixel(2, 1), PATH_RGB[1].0);
+        assert_eq!(image.pixel(1, 2), PATH_RGB[1].1);
+        assert_eq!(image.pixel(0, 1), TREE_RGB);
+
+        let mut ppm = vec![];
+        image.write_ppm(&mut ppm).unwrap();
+        assert!(ppm.starts_with(b"P6\n9 3\n255\n"));
+        assert_eq!(ppm.len(), 11 + 9 * 3 * 3);
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn png_round_trip() {
+        let map = input_generator(EXAMPLE);
+        let image = render_image(&map, &[Slope { dx: 3, dy: 1 }], 3);
+        let mut bytes = vec![];
+        image.write_png(&mut bytes).unwrap();
+
+        let mut reader = png::Decoder::new(&bytes[..]).read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        assert_eq!(
+            (info.width as usize, info.height as usize),
+            (image.width(), image.height())
+        );
+        assert_eq!(buf, image.pixels.concat());
+    }
+
+    #[test]
+    fn render() {
+        let map = input_generator("..#\n#..\n.#.");
+        let one = Renderer::new(&map).path((1, 1)).to_string();
+        assert_eq!(one, "O.#\n#O.\n.#O\n");
+
+        let two = Renderer::new(&map).path((1, 1)).path((2, 1));
+        assert_eq!(two.to_string(), "O.#\n#Oa\n.AO\n");
+
+        let mut out = vec![];
+        Renderer::new(&map)
+            .path((1, 2))
+            .ansi(true)
+            .write_to(&mut out)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\x1b[31mO\x1b[0m.#\n#..\n.\x1b[31mX\x1b[0m.\n"
+        );
+    }
 }
@@ -1,9 +1,70 @@
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::sync::OnceLock;
 
-use crate::record_parser::{RecordFactory, RecordParser};
+use crate::parse_error::ParseError;
+use crate::record_parser::{RecordFactory, RecordParser, Span};
 
-struct PassportRecordFactory;
+pub mod fixtures;
+
+/// A regex compiled the first time the expression is evaluated.
+macro_rules! static_regex {
+    ($pattern:expr) => {{
+        static RE: OnceLock<Regex> = OnceLock::new();
+        RE.get_or_init(|| Regex::new($pattern).unwrap())
+    }};
+}
+
+/// How the parser treats fields that don't fit a `Passport`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strictness {
+    /// Unknown keys, repeated keys and tokens that aren't `key:value` are
+    /// parse errors.
+    Strict,
+    /// Unknown keys go into `Passport::extra`, while repeats and malformed
+    /// tokens are kept as `FieldIssue`s for a `Ruleset` to judge.
+    Tolerant,
+}
+
+/// Something the tolerant parser let through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldIssue {
+    /// `key` was given again after the value that was kept.
+    Duplicate { key: String, value: String },
+    /// `token` has no `:`, or nothing before it.
+    Malformed { token: String },
+}
+
+struct PassportRecordFactory {
+    strictness: Strictness,
+}
+
+impl PassportRecordFactory {
+    fn issue(&self, record: &mut Passport, issue: FieldIssue) -> Result<(), ParseError> {
+        if self.strictness == Strictness::Tolerant {
+            record.issues.push(issue);
+            return Ok(());
+        }
+        Err(match issue {
+            FieldIssue::Duplicate { key, .. } => {
+                ParseError::unexpected(1, 1, key, "a key not given before")
+            }
+            FieldIssue::Malformed { token } => match token.find(':') {
+                None => {
+                    ParseError::missing(1, token.chars().count() + 1, "`:` between key and value")
+                }
+                Some(_) => ParseError::unexpected(1, 1, token, "a key before `:`"),
+            },
+        })
+    }
+}
 
 impl RecordFactory<Passport> for PassportRecordFactory {
     fn new_record(&self) -> Passport {
@@ -11,20 +72,73 @@ impl RecordFactory<Passport> for PassportRecordFactory {
     }
 
     fn accept_field(&self, record: &mut Passport, field: &str) {
-        let values: Vec<&str> = field.split(':').collect();
-        let key = values[0];
-        let value = Some(values[1].to_string());
-        match key {
-            "byr" => record.byr = value,
-            "iyr" => record.iyr = value,
-            "eyr" => record.eyr = value,
-            "hgt" => record.hgt = value,
-            "hcl" => record.hcl = value,
-            "ecl" => record.ecl = value,
-            "pid" => record.pid = value,
-            "cid" => record.cid = value,
-            _ => unreachable!(),
+        self.try_accept_field(record, field)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_accept_field(&self, record: &mut Passport, field: &str) -> Result<(), ParseError> {
+        let (key, value) = match field.split_once(':') {
+            Some((key, value)) if !key.is_empty() => (key, value),
+            _ => {
+                let token = field.into();
+                return self.issue(record, FieldIssue::Malformed { token });
+            }
+        };
+        let known = match key {
+            "byr" => Some(&mut record.byr),
+            "iyr" => Some(&mut record.iyr),
+            "eyr" => Some(&mut record.eyr),
+            "hgt" => Some(&mut record.hgt),
+            "hcl" => Some(&mut record.hcl),
+            "ecl" => Some(&mut record.ecl),
+            "pid" => Some(&mut record.pid),
+            "cid" => Some(&mut record.cid),
+            _ => None,
+        };
+        let first = match known {
+            Some(slot) if slot.is_none() => {
+                *slot = Some(value.into());
+                true
+            }
+            Some(_) => false,
+            None if self.strictness == Strictness::Strict => {
+                return Err(ParseError::unexpected(
+                    1,
+                    1,
+                    key,
+                    "one of byr, iyr, eyr, hgt, hcl, ecl, pid or cid",
+                ))
+            }
+            None => match record.extra.entry(key.into()) {
+                Entry::Vacant(entry) => {
+                    entry.insert(value.into());
+                    true
+                }
+                Entry::Occupied(_) => false,
+            },
+        };
+        if first {
+            return Ok(());
         }
+        let (key, value) = (key.into(), value.into());
+        self.issue(record, FieldIssue::Duplicate { key, value })
+    }
+
+    fn try_accept_field_at(
+        &self,
+        record: &mut Passport,
+        field: &str,
+        span: &Span,
+    ) -> Result<(), ParseError> {
+        self.try_accept_field(record, field)
+            .map_err(|e| e.offset(span.line, span.col))?;
+        if let Some((key, _)) = field.split_once(':').filter(|(key, _)| !key.is_empty()) {
+            record
+                .spans
+                .entry(key.into())
+                .or_insert_with(|| span.clone());
+        }
+        Ok(())
     }
 }
 
@@ -38,85 +152,1326 @@ pub struct Passport {
     ecl: Option<String>, // (Eye Color)
     pid: Option<String>, // (Passport ID)
     cid: Option<String>, // (Country ID)
+    /// Fields with keys other than the eight above, when parsed tolerantly.
+    /// In JSON they sit alongside the known ones.
+    #[serde(flatten)]
+    extra: BTreeMap<String, String>,
+    #[serde(skip)]
+    issues: Vec<FieldIssue>,
+    #[serde(skip)]
+    spans: BTreeMap<String, Span>,
 }
 
 impl Passport {
-    fn valid_date(s: &str, lower: u32, upper: u32) -> bool {
-        let re = Regex::new(r"^\d{4}$").unwrap();
-        if !re.is_match(s) {
-            return false;
+    /// Where the field `key` was read from, if this passport was parsed.
+    pub fn span(&self, key: &str) -> Option<&Span> {
+        self.spans.get(key)
+    }
+
+    /// Fields with unknown keys, so a ruleset can still validate them.
+    pub fn extra(&self) -> &BTreeMap<String, String> {
+        &self.extra
+    }
+
+    /// Repeated and malformed fields, in the order they were found.
+    pub fn issues(&self) -> &[FieldIssue] {
+        &self.issues
+    }
+
+    /// The value of field `key`, if the passport has it.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let value = match key {
+            "byr" => &self.byr,
+            "iyr" => &self.iyr,
+            "eyr" => &self.eyr,
+            "hgt" => &self.hgt,
+            "hcl" => &self.hcl,
+            "ecl" => &self.ecl,
+            "pid" => &self.pid,
+            "cid" => &self.cid,
+            _ => return self.extra.get(key).map(String::as_str),
+        };
+        value.as_deref()
+    }
+
+    /// Whether every required field in `rules` is present and passes all
+    /// of its validators.
+    pub fn validate_with(&self, rules: &Ruleset) -> bool {
+        rules.fields.iter().all(|(key, rule)| match self.get(key) {
+            Some(value) => rule.validators.iter().all(|v| v.check(value)),
+            None => rule.optional,
+        }) && self.rejections(rules).next().is_none()
+    }
+
+    /// Failures for whatever the tolerant parser let through that `rules`
+    /// rejects.
+    fn rejections<'a>(&'a self, rules: &'a Ruleset) -> impl Iterator<Item = FieldReport> + 'a {
+        let reject = rules.reject;
+        let invalid = |field: &str, reason: String| FieldReport {
+            field: field.into(),
+            status: FieldStatus::Invalid { reason },
+        };
+        let extra = self
+            .extra
+            .keys()
+            .filter(move |key| reject.extra && !rules.fields.contains_key(*key))
+            .map(move |key| invalid(key, format!("{} is not a known field", key)));
+        let issues = self.issues.iter().filter_map(move |issue| match issue {
+            FieldIssue::Duplicate { key, .. } if reject.duplicate => {
+                Some(invalid(key, format!("{} given more than once", key)))
+            }
+            FieldIssue::Malformed { token } if reject.malformed => {
+                Some(invalid(token, format!("{:?} is not key:value", token)))
+            }
+            _ => None,
+        });
+        extra.chain(issues)
+    }
+
+    /// `validate_with` the built in strict or required-fields-only ruleset.
+    pub fn validate(&self, strict: bool) -> bool {
+        let rules = if strict {
+            Ruleset::strict()
+        } else {
+            Ruleset::required()
+        };
+        self.validate_with(rules)
+    }
+}
+
+/// An inclusive range of numbers.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds {
+    pub min: u32,
+    pub max: u32,
+}
+
+impl Bounds {
+    /// Checks `n`, read from `value`, is within bounds.
+    fn verify(&self, n: u32, value: &str) -> Result<(), String> {
+        if n < self.min {
+            Err(format!("{} below min {}", value, self.min))
+        } else if n > self.max {
+            Err(format!("{} above max {}", value, self.max))
+        } else {
+            Ok(())
         }
-        let date: u32 = s.parse().unwrap();
-        date >= lower && date <= upper
     }
+}
 
-    fn valid_height(s: &str) -> bool {
-        let re = Regex::new(r"^\d+(cm|in)$").unwrap();
-        if !re.is_match(s) {
-            return false;
+/// One check of a field's value. In JSON each is an object keyed by the
+/// validator name, e.g. `{"year_range": {"min": 1920, "max": 2002}}`, except
+/// `"hex_color"` which takes no arguments.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Validator {
+    /// Four digits between the bounds.
+    YearRange(Bounds),
+    /// A number directly followed by one of the units, within its bounds,
+    /// e.g. `{"cm": {"min": 150, "max": 193}}`.
+    UnitRange(BTreeMap<String, Bounds>),
+    /// `#` and six lowercase hex digits.
+    HexColor,
+    /// Exactly one of the listed values.
+    Enum(Vec<String>),
+    /// The whole value matches the pattern.
+    Regex(Pattern),
+}
+
+impl Validator {
+    pub fn check(&self, value: &str) -> bool {
+        self.verify(value).is_ok()
+    }
+
+    /// Checks `value`, explaining what is wrong with it on failure, e.g.
+    /// `200cm above max 193`.
+    pub fn verify(&self, value: &str) -> Result<(), String> {
+        let fail = |why: &str| Err(format!("{} {}", value, why));
+        match self {
+            Validator::YearRange(bounds) => {
+                let year: Year = value.parse().map_err(|e: FieldParseError| e.to_string())?;
+                bounds.verify(year.0.into(), value)
+            }
+            Validator::UnitRange(units) => {
+                let re = static_regex!(r"^([0-9]+)(\D*)$");
+                let names = || units.keys().cloned().collect::<Vec<_>>().join(" or ");
+                let caps = match re.captures(value) {
+                    Some(caps) => caps,
+                    None => return fail(&format!("is not a number in {}", names())),
+                };
+                let bounds = match units.get(&caps[2]) {
+                    Some(bounds) => bounds,
+                    None if caps[2].is_empty() => {
+                        return fail(&format!("has no unit, expected {}", names()))
+                    }
+                    None => return fail(&format!("has unknown unit, expected {}", names())),
+                };
+                match caps[1].parse() {
+                    Ok(n) => bounds.verify(n, value),
+                    Err(_) => fail(&format!("above max {}", bounds.max)),
+                }
+            }
+            Validator::HexColor => value
+                .parse::<HexColor>()
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            Validator::Enum(values) => {
+                if values.iter().any(|v| v == value) {
+                    Ok(())
+                } else {
+                    fail(&format!("is not one of {}", values.join(", ")))
+                }
+            }
+            Validator::Regex(pattern) => {
+                if pattern.is_match(value) {
+                    Ok(())
+                } else {
+                    fail(&format!("does not match /{}/", pattern))
+                }
+            }
         }
-        let num: u32 = (&s[..s.len() - 2]).parse().unwrap();
-        if s.ends_with("cm") {
-            num >= 150 && num <= 193
-        } else {
-            num >= 59 && num <= 76
+    }
+}
+
+/// A regex validator's pattern, compiled once when the ruleset is loaded.
+/// It must match the whole value, so needs no anchors of its own.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub struct Pattern {
+    source: String,
+    regex: Regex,
+}
+
+impl Pattern {
+    pub fn new(source: &str) -> Result<Self, regex::Error> {
+        Ok(Pattern {
+            source: source.into(),
+            regex: Regex::new(&format!("^(?:{})$", source))?,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    pub fn is_match(&self, value: &str) -> bool {
+        self.regex.is_match(value)
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for Pattern {}
+
+impl TryFrom<String> for Pattern {
+    type Error = String;
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        Pattern::new(&source).map_err(|e| format!("bad regex /{}/: {}", source, e))
+    }
+}
+
+impl From<Pattern> for String {
+    fn from(pattern: Pattern) -> Self {
+        pattern.source
+    }
+}
+
+/// What a ruleset asks of one field.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldRule {
+    /// A missing optional field passes, a present one is still validated.
+    #[serde(default)]
+    pub optional: bool,
+    #[serde(default)]
+    pub validators: Vec<Validator>,
+}
+
+/// Which passport fields must be present, and what their values must look
+/// like, keyed by field name. Loaded from JSON so that policy changes need no
+/// code change; see `rules/day4/` for the built in rulesets.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Ruleset {
+    #[serde(default)]
+    pub name: String,
+    pub fields: BTreeMap<String, FieldRule>,
+    #[serde(default)]
+    pub reject: Rejections,
+}
+
+/// Which of the things a tolerant parse lets through fail a passport. None
+/// do unless asked for, e.g. `"reject": {"duplicate": true}`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Rejections {
+    /// Unknown fields that the ruleset doesn't list. Listed ones are
+    /// validated like any other field.
+    pub extra: bool,
+    pub duplicate: bool,
+    pub malformed: bool,
+}
+
+impl Ruleset {
+    /// Parses a ruleset. Regex validators are compiled here, so a pattern
+    /// that doesn't compile is an error.
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    pub fn from_reader(reader: impl io::Read) -> Result<Self, serde_json::Error> {
+        serde_json::from_reader(reader)
+    }
+
+    /// All fields but `cid` present, whatever their values (part 1).
+    pub fn required() -> &'static Ruleset {
+        static RULES: OnceLock<Ruleset> = OnceLock::new();
+        RULES.get_or_init(|| {
+            Ruleset::from_json(include_str!("../rules/day4/required.json")).unwrap()
+        })
+    }
+
+    /// The required fields with valid values (part 2).
+    pub fn strict() -> &'static Ruleset {
+        static RULES: OnceLock<Ruleset> = OnceLock::new();
+        RULES.get_or_init(|| Ruleset::from_json(include_str!("../rules/day4/strict.json")).unwrap())
+    }
+}
+
+/// What became of one field when a passport was checked.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum FieldStatus {
+    Valid,
+    /// An optional field that wasn't given.
+    Absent,
+    Missing,
+    Invalid {
+        reason: String,
+    },
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FieldReport {
+    pub field: String,
+    #[serde(flatten)]
+    pub status: FieldStatus,
+}
+
+impl FieldReport {
+    pub fn is_failure(&self) -> bool {
+        matches!(
+            self.status,
+            FieldStatus::Missing | FieldStatus::Invalid { .. }
+        )
+    }
+}
+
+/// Every field of one passport checked against a ruleset, in the ruleset's
+/// field order. `Display` prints it as a table.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub ruleset: String,
+    pub fields: Vec<FieldReport>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &FieldReport> {
+        self.fields.iter().filter(|f| f.is_failure())
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<5}  {:<7}  reason", "field", "status")?;
+        for report in &self.fields {
+            let (status, reason) = match &report.status {
+                FieldStatus::Valid => ("valid", ""),
+                FieldStatus::Absent => ("absent", ""),
+                FieldStatus::Missing => ("missing", ""),
+                FieldStatus::Invalid { reason } => ("invalid", reason.as_str()),
+            };
+            let line = format!("{:<5}  {:<7}  {}", report.field, status, reason);
+            writeln!(f, "{}", line.trim_end())?;
         }
+        Ok(())
     }
+}
 
-    fn valid_hair(s: &str) -> bool {
-        let re = Regex::new(r"^\#([0-9a-f]{6})$").unwrap();
-        re.is_match(s)
+impl Passport {
+    /// Checks each field in `rules`, stopping at the first validator a
+    /// field fails.
+    pub fn check(&self, rules: &Ruleset) -> ValidationReport {
+        let fields = rules
+            .fields
+            .iter()
+            .map(|(key, rule)| {
+                let status = match self.get(key) {
+                    Some(value) => match rule.validators.iter().try_for_each(|v| v.verify(value)) {
+                        Ok(()) => FieldStatus::Valid,
+                        Err(why) => FieldStatus::Invalid {
+                            reason: format!("{} {}", key, why),
+                        },
+                    },
+                    None if rule.optional => FieldStatus::Absent,
+                    None => FieldStatus::Missing,
+                };
+                FieldReport {
+                    field: key.clone(),
+                    status,
+                }
+            })
+            .chain(self.rejections(rules))
+            .collect();
+        ValidationReport {
+            ruleset: rules.name.clone(),
+            fields,
+        }
     }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FieldFailures {
+    pub missing: usize,
+    pub invalid: usize,
+}
 
-    fn valid_eye(s: &str) -> bool {
-        let re = Regex::new(r"^(amb|blu|brn|gry|grn|hzl|oth)$").unwrap();
-        re.is_match(s)
+impl FieldFailures {
+    pub fn total(&self) -> usize {
+        self.missing + self.invalid
     }
+}
+
+/// Which fields a batch of passports failed on, for finding the rule that
+/// rejects the most. `Display` prints it as a table.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchSummary {
+    pub ruleset: String,
+    pub passports: usize,
+    pub valid: usize,
+    pub failures: BTreeMap<String, FieldFailures>,
+}
 
-    fn valid_pid(s: &str) -> bool {
-        let re = Regex::new(r"^([0-9]{9})$").unwrap();
-        re.is_match(s)
+impl BatchSummary {
+    pub fn from_reports<'a>(reports: impl IntoIterator<Item = &'a ValidationReport>) -> Self {
+        let mut summary = BatchSummary::default();
+        for report in reports {
+            summary.add(report);
+        }
+        summary
     }
 
-    pub fn validate(&self, strict: bool) -> bool {
-        match self {
-            Passport {
-                byr: Some(byr), // Option<String>, // (Birth Year)
-                iyr: Some(iyr), // Option<String>, // (Issue Year)
-                eyr: Some(eyr), // Option<String>, // (Expiration Year)
-                hgt: Some(hgt), // Option<String>, // (Height)
-                hcl: Some(hcl), // Option<String>, // (Hair Color)
-                ecl: Some(ecl), // Option<String>, // (Eye Color)
-                pid: Some(pid), // Option<String>, // (Passport ID)
-                cid: _,         // Option<String>, // (Country ID)
-            } if !strict
-                || (Self::valid_date(byr, 1920, 2002)
-                    && Self::valid_date(iyr, 2010, 2020)
-                    && Self::valid_date(eyr, 2020, 2030)
-                    && Self::valid_height(hgt)
-                    && Self::valid_hair(hcl)
-                    && Self::valid_eye(ecl)
-                    && Self::valid_pid(pid)) =>
-            {
-                true
+    pub fn add(&mut self, report: &ValidationReport) {
+        self.ruleset = report.ruleset.clone();
+        self.passports += 1;
+        if report.is_valid() {
+            self.valid += 1;
+        }
+        for field in report.failures() {
+            let failures = self.failures.entry(field.field.clone()).or_default();
+            match field.status {
+                FieldStatus::Missing => failures.missing += 1,
+                _ => failures.invalid += 1,
             }
-            _ => false,
         }
     }
+
+    /// Field failures over all passports. One passport can fail several.
+    pub fn total_failures(&self) -> usize {
+        self.failures.values().map(FieldFailures::total).sum()
+    }
+
+    /// The percentage of all field failures that are on `field`.
+    pub fn share(&self, field: &str) -> f64 {
+        let total = self.total_failures();
+        match self.failures.get(field) {
+            Some(failures) if total > 0 => 100.0 * failures.total() as f64 / total as f64,
+            _ => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for BatchSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{}: {} of {} passports valid, {} field failures",
+            self.ruleset,
+            self.valid,
+            self.passports,
+            self.total_failures()
+        )?;
+        writeln!(
+            f,
+            "{:<5}  {:>7}  {:>7}  {:>5}",
+            "field", "missing", "invalid", "share"
+        )?;
+        for (field, failures) in &self.failures {
+            writeln!(
+                f,
+                "{:<5}  {:>7}  {:>7}  {:>4.0}%",
+                field,
+                failures.missing,
+                failures.invalid,
+                self.share(field)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks every passport against `rules` and totals the failures.
+pub fn summarize(passports: &[Passport], rules: &Ruleset) -> BatchSummary {
+    BatchSummary::from_reports(&passports.iter().map(|p| p.check(rules)).collect::<Vec<_>>())
+}
+
+/// Why a field's value couldn't be read as its type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldParseError {
+    pub value: String,
+    pub expected: &'static str,
+}
+
+impl FieldParseError {
+    fn new(value: &str, expected: &'static str) -> Self {
+        FieldParseError {
+            value: value.into(),
+            expected,
+        }
+    }
+}
+
+impl fmt::Display for FieldParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} is not {}", self.value, self.expected)
+    }
+}
+
+impl Error for FieldParseError {}
+
+/// A four digit year, `byr`, `iyr` or `eyr`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Year(pub u16);
+
+impl FromStr for Year {
+    type Err = FieldParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 4 || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(FieldParseError::new(s, "a four digit year"));
+        }
+        Ok(Year(s.parse().unwrap()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HeightUnit {
+    Cm,
+    In,
+}
+
+/// `hgt`, a number directly followed by `cm` or `in`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Height {
+    pub value: u32,
+    pub unit: HeightUnit,
+}
+
+impl FromStr for Height {
+    type Err = FieldParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || FieldParseError::new(s, "a number of cm or in");
+        let (digits, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).ok_or_else(err)?);
+        let unit = match unit {
+            "cm" => HeightUnit::Cm,
+            "in" => HeightUnit::In,
+            _ => return Err(err()),
+        };
+        if digits.is_empty() {
+            return Err(err());
+        }
+        let value = digits.parse().map_err(|_| err())?;
+        Ok(Height { value, unit })
+    }
+}
+
+/// `hcl`, `#` and six lowercase hex digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HexColor(pub [u8; 3]);
+
+impl FromStr for HexColor {
+    type Err = FieldParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || FieldParseError::new(s, "`#` and six hex digits");
+        let hex = s.strip_prefix('#').ok_or_else(err)?;
+        if hex.len() != 6 || !hex.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+            return Err(err());
+        }
+        let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+        Ok(HexColor([byte(0), byte(2), byte(4)]))
+    }
+}
+
+/// `ecl`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EyeColor {
+    Amb,
+    Blu,
+    Brn,
+    Gry,
+    Grn,
+    Hzl,
+    Oth,
+}
+
+impl FromStr for EyeColor {
+    type Err = FieldParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "amb" => EyeColor::Amb,
+            "blu" => EyeColor::Blu,
+            "brn" => EyeColor::Brn,
+            "gry" => EyeColor::Gry,
+            "grn" => EyeColor::Grn,
+            "hzl" => EyeColor::Hzl,
+            "oth" => EyeColor::Oth,
+            _ => {
+                return Err(FieldParseError::new(
+                    s,
+                    "one of amb, blu, brn, gry, grn, hzl or oth",
+                ))
+            }
+        })
+    }
+}
+
+/// `pid`, nine digits. Leading zeros are kept when displayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PassportId(pub u32);
+
+impl FromStr for PassportId {
+    type Err = FieldParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 9 || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(FieldParseError::new(s, "a nine digit passport id"));
+        }
+        Ok(PassportId(s.parse().unwrap()))
+    }
+}
+
+impl fmt::Display for PassportId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:09}", self.0)
+    }
+}
+
+/// A passport that passed the strict ruleset, with its fields read into
+/// types. The only way to get one is `try_from` a `Passport`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidatedPassport {
+    byr: Year,
+    iyr: Year,
+    eyr: Year,
+    hgt: Height,
+    hcl: HexColor,
+    ecl: EyeColor,
+    pid: PassportId,
+    cid: Option<String>,
+}
+
+impl ValidatedPassport {
+    pub fn birth_year(&self) -> Year {
+        self.byr
+    }
+
+    pub fn issue_year(&self) -> Year {
+        self.iyr
+    }
+
+    pub fn expiration_year(&self) -> Year {
+        self.eyr
+    }
+
+    pub fn height(&self) -> Height {
+        self.hgt
+    }
+
+    pub fn hair_color(&self) -> HexColor {
+        self.hcl
+    }
+
+    pub fn eye_color(&self) -> EyeColor {
+        self.ecl
+    }
+
+    pub fn passport_id(&self) -> PassportId {
+        self.pid
+    }
+
+    pub fn country_id(&self) -> Option<&str> {
+        self.cid.as_deref()
+    }
+}
+
+impl TryFrom<&Passport> for ValidatedPassport {
+    /// The strict ruleset's report. If the ruleset let through a value the
+    /// types can't hold, say a new eye colour, that field is marked invalid.
+    type Error = ValidationReport;
+
+    fn try_from(passport: &Passport) -> Result<Self, Self::Error> {
+        let mut report = passport.check(Ruleset::strict());
+        if !report.is_valid() {
+            return Err(report);
+        }
+        let byr = typed(passport, "byr", &mut report);
+        let iyr = typed(passport, "iyr", &mut report);
+        let eyr = typed(passport, "eyr", &mut report);
+        let hgt = typed(passport, "hgt", &mut report);
+        let hcl = typed(passport, "hcl", &mut report);
+        let ecl = typed(passport, "ecl", &mut report);
+        let pid = typed(passport, "pid", &mut report);
+        match (byr, iyr, eyr, hgt, hcl, ecl, pid) {
+            (Some(byr), Some(iyr), Some(eyr), Some(hgt), Some(hcl), Some(ecl), Some(pid)) => {
+                Ok(ValidatedPassport {
+                    byr,
+                    iyr,
+                    eyr,
+                    hgt,
+                    hcl,
+                    ecl,
+                    pid,
+                    cid: passport.cid.clone(),
+                })
+            }
+            _ => Err(report),
+        }
+    }
+}
+
+/// Field `key` of `passport` read as a `T`, or `None` with the field marked
+/// invalid in `report`.
+fn typed<T>(passport: &Passport, key: &str, report: &mut ValidationReport) -> Option<T>
+where
+    T: FromStr<Err = FieldParseError>,
+{
+    let e = match passport.get(key).unwrap_or_default().parse() {
+        Ok(value) => return Some(value),
+        Err(e) => e,
+    };
+    let status = FieldStatus::Invalid {
+        reason: format!("{} {}", key, e),
+    };
+    match report.fields.iter_mut().find(|f| f.field == key) {
+        Some(field) => field.status = status,
+        None => report.fields.push(FieldReport {
+            field: key.into(),
+            status,
+        }),
+    }
+    None
+}
+
+const FIELD_ORDER: [&str; 8] = ["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid", "cid"];
+
+impl Passport {
+    /// Every field the passport has, the eight known ones first.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &str)> {
+        let known = FIELD_ORDER
+            .iter()
+            .filter_map(move |key| Some((*key, self.get(key)?)));
+        known.chain(self.extra.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+    }
+}
+
+/// Writes passports in the puzzle's format, one per line with a blank line
+/// between them, so `generate` reads back the same fields. Repeated and
+/// malformed fields were not kept, so are not written.
+///
+/// A passport that wouldn't read back the same is an `InvalidData` error
+/// naming it: one with no fields, an empty key, a key holding whitespace or
+/// `:`, or a value holding whitespace.
+pub fn write_records(passports: &[Passport], out: &mut impl io::Write) -> io::Result<()> {
+    for (i, passport) in passports.iter().enumerate() {
+        let invalid = |why: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("passport {}: {}", i + 1, why),
+            )
+        };
+        let mut fields = vec![];
+        for (key, value) in passport.fields() {
+            if key.is_empty() || key.contains(|c: char| c == ':' || c.is_whitespace()) {
+                return Err(invalid(format!("{:?} can't be written as a key", key)));
+            }
+            if value.contains(char::is_whitespace) {
+                return Err(invalid(format!(
+                    "{} value {:?} holds whitespace",
+                    key, value
+                )));
+            }
+            fields.push(format!("{}:{}", key, value));
+        }
+        if fields.is_empty() {
+            return Err(invalid("has no fields to write".into()));
+        }
+        if i > 0 {
+            writeln!(out)?;
+        }
+        writeln!(out, "{}", fields.join(" "))?;
+    }
+    Ok(())
+}
+
+/// Writes one JSON object per passport per line.
+pub fn write_json_lines(passports: &[Passport], out: &mut impl io::Write) -> io::Result<()> {
+    for passport in passports {
+        serde_json::to_writer(&mut *out, passport)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Reads passports written by `write_json_lines`, or by hand: missing
+/// fields may be left out, unknown keys go into `extra`, blank lines are
+/// skipped. A line that isn't a passport is an `InvalidData` error naming
+/// the line.
+pub fn read_json_lines(input: impl io::BufRead) -> io::Result<Vec<Passport>> {
+    let mut passports = vec![];
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let passport = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", i + 1, e))
+        })?;
+        passports.push(passport);
+    }
+    Ok(passports)
+}
+
+/// Writes a CSV table with a header row: the eight known fields, then a
+/// column for every extra key in the batch. Missing fields are empty.
+pub fn write_csv(passports: &[Passport], out: &mut impl io::Write) -> io::Result<()> {
+    let extra: BTreeSet<&str> = passports
+        .iter()
+        .flat_map(|p| p.extra.keys().map(String::as_str))
+        .collect();
+    let columns: Vec<&str> = FIELD_ORDER.iter().copied().chain(extra).collect();
+    let row = |cells: Vec<&str>| -> String {
+        let cells: Vec<String> = cells.into_iter().map(csv_escape).collect();
+        cells.join(",")
+    };
+    writeln!(out, "{}", row(columns.clone()))?;
+    for passport in passports {
+        let cells = columns
+            .iter()
+            .map(|key| passport.get(key).unwrap_or_default())
+            .collect();
+        writeln!(out, "{}", row(cells))?;
+    }
+    Ok(())
+}
+
+/// Quotes a CSV cell if it holds a comma, quote or line break.
+fn csv_escape(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.into()
+    }
+}
+
+/// How many passports pass `rules`.
+pub fn count_valid(passports: &[Passport], rules: &Ruleset) -> u64 {
+    passports.iter().filter(|p| p.validate_with(rules)).count() as u64
+}
+
+pub fn try_generate_with(input: &str, strictness: Strictness) -> Result<Vec<Passport>, ParseError> {
+    let parser: RecordParser = Default::default();
+    parser.try_parse(PassportRecordFactory { strictness }, input)
+}
+
+/// Parses tolerantly, leaving the odd fields for validation to judge. Use
+/// `try_generate_with` and `Strictness::Strict` to fail on them instead.
+pub fn try_generate(input: &str) -> Result<Vec<Passport>, ParseError> {
+    try_generate_with(input, Strictness::Tolerant)
 }
 
 #[aoc_generator(day4)]
 pub fn generate(input: &str) -> Vec<Passport> {
-    let mut parser: RecordParser = Default::default();
-    parser.parse(PassportRecordFactory, input)
+    try_generate(input).unwrap_or_else(|e| panic!("{}", e))
 }
 
 #[aoc(day4, part1)]
 pub fn part1(passports: &[Passport]) -> u64 {
-    passports.iter().filter(|p| p.validate(false)).count() as u64
+    count_valid(passports, Ruleset::required())
 }
 
 #[aoc(day4, part2)]
 pub fn part2(passports: &[Passport]) -> u64 {
-    passports.iter().filter(|p| p.validate(true)).count() as u64
+    count_valid(passports, Ruleset::strict())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn generator_errors() {
+        let strict = |input| try_generate_with(input, Strictness::Strict);
+        let input = indoc! {"
+            ecl:gry pid:860033327 eyr:2020 hcl:#fffffd
+            byr:1937 iyr:2017 cid:147 hgt:183cm
+
+            iyr:2013 ecl:amb cid:350
+            eyr:2023 pid:028048884 hair:#cfa07d byr:1929
+        "};
+        assert_eq!(
+            strict(input).unwrap_err(),
+            ParseError::unexpected(
+                5,
+                24,
+                "hair",
+                "one of byr, iyr, eyr, hgt, hcl, ecl, pid or cid"
+            )
+        );
+
+        let input = "ecl:gry pid:860033327\neyr2020 hcl:#fffffd\n";
+        assert_eq!(
+            strict(input).unwrap_err(),
+            ParseError::missing(2, 8, "`:` between key and value")
+        );
+    }
+
+    #[test]
+    fn serde_factory_matches_generate() {
+        use crate::record_parser::SerdeRecordFactory;
+        let input = indoc! {"
+            ecl:gry pid:860033327 eyr:2020 hcl:#fffffd
+            byr:1937 iyr:2017 cid:147 hgt:183cm
+
+            hcl:#cfa07d eyr:2025 pid:166559648
+            iyr:2011 ecl:brn hgt:59in
+        "};
+        let parser: RecordParser = Default::default();
+        let passports = SerdeRecordFactory::<Passport>::new()
+            .parse(&parser, input)
+            .unwrap();
+        assert_eq!(
+            serde_json::to_string(&passports).unwrap(),
+            serde_json::to_string(&generate(input)).unwrap()
+        );
+    }
+
+    #[test]
+    fn field_spans() {
+        let input = "ecl:gry pid:860033327\n\nhcl:#cfa07d\nhgt:59in eyr:2025\n";
+        let passports = generate(input);
+        let hgt = passports[1].span("hgt").unwrap();
+        assert_eq!((hgt.record_index, hgt.line, hgt.col), (1, 4, 1));
+        assert_eq!(&input[hgt.byte_range.clone()], "hgt:59in");
+        let eyr = passports[1].span("eyr").unwrap();
+        assert_eq!((eyr.line, eyr.col), (4, 10));
+        assert_eq!(passports[0].span("hgt"), None);
+    }
+
+    const INVALID: &str = indoc! {"
+        eyr:1972 cid:100
+        hcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018 byr:1926
+
+        iyr:2019
+        hcl:#602927 eyr:1967 hgt:170cm
+        ecl:grn pid:012533040 byr:1946
+
+        hcl:dab227 iyr:2012
+        ecl:brn hgt:182cm pid:021572410 eyr:2020 byr:1992 cid:277
+
+        hgt:59cm ecl:zzz
+        eyr:2038 hcl:74454a iyr:2023
+        pid:3556412378 byr:2007
+    "};
+
+    const VALID: &str = indoc! {"
+        pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980
+        hcl:#623a2f
+
+        eyr:2029 ecl:blu cid:129 byr:1989
+        iyr:2014 pid:896056539 hcl:#a97842 hgt:165cm
+
+        hcl:#888785
+        hgt:164cm byr:2001 iyr:2015 cid:88
+        pid:545766238 ecl:hzl
+        eyr:2022
+
+        iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719
+    "};
+
+    #[test]
+    fn built_in_rulesets() {
+        assert_eq!(part1(&generate(INVALID)), 4);
+        assert_eq!(part2(&generate(INVALID)), 0);
+        assert_eq!(part2(&generate(VALID)), 4);
+        assert_eq!(Ruleset::strict().name, "strict");
+    }
+
+    #[test]
+    fn validators() {
+        let year = Validator::YearRange(Bounds {
+            min: 1920,
+            max: 2002,
+        });
+        assert!(year.check("2002"));
+        assert!(!year.check("2003"));
+        assert!(!year.check("02002"));
+        assert_eq!(
+            year.verify("١٩٢٠"),
+            Err("١٩٢٠ is not a four digit year".into())
+        );
+        let hgt = &Ruleset::strict().fields["hgt"].validators[0];
+        assert!(hgt.check("60in"));
+        assert!(hgt.check("190cm"));
+        assert!(!hgt.check("190in"));
+        assert!(!hgt.check("190"));
+        assert!(!hgt.check("99999999999999999999cm"));
+        assert_eq!(
+            hgt.verify("١٩٠cm"),
+            Err("١٩٠cm is not a number in cm or in".into())
+        );
+        assert!(Validator::HexColor.check("#123abc"));
+        assert!(!Validator::HexColor.check("#123abz"));
+        assert!(!Validator::HexColor.check("123abc"));
+        let pid = Validator::Regex(Pattern::new("[0-9]{9}").unwrap());
+        assert!(pid.check("000000001"));
+        assert!(!pid.check("0123456789"));
+    }
+
+    #[test]
+    fn custom_rulesets() {
+        let json = Ruleset::strict().fields["ecl"].clone();
+        assert_eq!(
+            serde_json::to_string(&json).unwrap(),
+            r#"{"optional":false,"validators":[{"enum":["amb","blu","brn","gry","grn","hzl","oth"]}]}"#
+        );
+
+        let purple = Ruleset::from_json(
+            r#"{
+                "name": "purple eyes",
+                "fields": {
+                    "ecl": { "validators": [{ "enum": ["prp"] }] },
+                    "hgt": { "optional": true, "validators": [{ "unit_range": { "cm": { "min": 150, "max": 160 } } }] }
+                }
+            }"#,
+        )
+        .unwrap();
+        let passports = generate("ecl:prp\n\necl:prp hgt:170cm\n\necl:blu\n\nhgt:155cm");
+        assert_eq!(count_valid(&passports, &purple), 1);
+        assert_eq!(count_valid(&passports, Ruleset::required()), 0);
+
+        let err = Ruleset::from_json(r#"{"fields": {"pid": {"validators": [{"regex": "("}]}}}"#)
+            .unwrap_err();
+        assert!(err.to_string().starts_with("bad regex /(/"));
+        assert!(Ruleset::from_json(r#"{"fields": {"pid": {"validators": ["even"]}}}"#).is_err());
+    }
+
+    #[test]
+    fn validation_report() {
+        let passports = generate(INVALID);
+        let report = passports[0].check(Ruleset::strict());
+        assert!(!report.is_valid());
+        assert_eq!(
+            report.to_string(),
+            indoc! {"
+                field  status   reason
+                byr    valid
+                cid    valid
+                ecl    valid
+                eyr    invalid  eyr 1972 below min 2020
+                hcl    valid
+                hgt    invalid  hgt 170 has no unit, expected cm or in
+                iyr    valid
+                pid    invalid  pid 186cm does not match /[0-9]{9}/
+            "}
+        );
+        assert_eq!(
+            serde_json::to_string(&report.fields[3]).unwrap(),
+            r#"{"field":"eyr","status":"invalid","reason":"eyr 1972 below min 2020"}"#
+        );
+
+        let report = generate("hgt:200cm")[0].check(Ruleset::strict());
+        assert_eq!(
+            report.fields[5].status,
+            FieldStatus::Invalid {
+                reason: "hgt 200cm above max 193".into()
+            }
+        );
+        assert_eq!(report.fields[0].status, FieldStatus::Missing);
+        assert_eq!(report.fields[1].status, FieldStatus::Absent);
+        assert!(generate(VALID)[0].check(Ruleset::strict()).is_valid());
+    }
+
+    #[test]
+    fn batch_summary() {
+        let mut passports = generate(INVALID);
+        passports.extend(generate(VALID));
+        let summary = summarize(&passports, Ruleset::strict());
+        assert_eq!((summary.passports, summary.valid), (8, 4));
+        assert_eq!(summary.total_failures(), 12);
+        assert_eq!(
+            summary.failures["eyr"],
+            FieldFailures {
+                missing: 0,
+                invalid: 3
+            }
+        );
+        assert_eq!(summary.share("eyr"), 25.0);
+        assert_eq!(summary.share("cid"), 0.0);
+        assert_eq!(
+            summary.to_string().lines().take(3).collect::<Vec<_>>(),
+            [
+                "strict: 4 of 8 passports valid, 12 field failures",
+                "field  missing  invalid  share",
+                "byr          0        1     8%",
+            ]
+        );
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(json["failures"]["eyr"]["invalid"], 3);
+        assert_eq!(json["valid"], 4);
+    }
+
+    #[test]
+    fn tolerant_fields() {
+        let input = "byr:1980 hair:#123abc byr:1990 eyr2020 :x\nhair:red hgt:170cm\n\nbyr:1937";
+        let passports = generate(input);
+        let p = &passports[0];
+        assert_eq!(p.get("byr"), Some("1980"));
+        assert_eq!(p.get("hair"), Some("#123abc"));
+        assert_eq!(p.extra().len(), 1);
+        assert_eq!(
+            p.issues(),
+            [
+                FieldIssue::Duplicate {
+                    key: "byr".into(),
+                    value: "1990".into()
+                },
+                FieldIssue::Malformed {
+                    token: "eyr2020".into()
+                },
+                FieldIssue::Malformed { token: ":x".into() },
+                FieldIssue::Duplicate {
+                    key: "hair".into(),
+                    value: "red".into()
+                },
+            ]
+        );
+        assert_eq!(p.span("byr").unwrap().col, 1);
+        assert_eq!(p.span(""), None);
+        assert!(passports[1].issues().is_empty());
+        assert_eq!(try_generate(input).unwrap()[0].issues(), p.issues());
+
+        let strict = |input| try_generate_with(input, Strictness::Strict);
+        assert_eq!(
+            strict("byr:1980 byr:1990").unwrap_err(),
+            ParseError::unexpected(1, 10, "byr", "a key not given before")
+        );
+        assert_eq!(
+            strict("byr:1980\n:x").unwrap_err(),
+            ParseError::unexpected(2, 1, ":x", "a key before `:`")
+        );
+    }
+
+    #[test]
+    fn rulesets_judge_odd_fields() {
+        let passports = generate("byr:1980 hair:#123abc byr:1990 eyr2020");
+        let lenient = Ruleset::from_json(r#"{"fields": {"byr": {}}}"#).unwrap();
+        assert!(passports[0].validate_with(&lenient));
+
+        let strict = Ruleset::from_json(
+            r#"{
+                "name": "no surprises",
+                "fields": {"byr": {}},
+                "reject": {"extra": true, "duplicate": true, "malformed": true}
+            }"#,
+        )
+        .unwrap();
+        assert!(!passports[0].validate_with(&strict));
+        let report = passports[0].check(&strict);
+        let reasons: Vec<_> = report
+            .failures()
+            .map(|f| match &f.status {
+                FieldStatus::Invalid { reason } => reason.as_str(),
+                _ => "",
+            })
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                "hair is not a known field",
+                "byr given more than once",
+                "\"eyr2020\" is not key:value"
+            ]
+        );
+
+        let hair = Ruleset::from_json(
+            r#"{
+                "fields": {"hair": {"validators": ["hex_color"]}},
+                "reject": {"extra": true}
+            }"#,
+        )
+        .unwrap();
+        assert!(passports[0].validate_with(&hair));
+    }
+
+    #[test]
+    fn typed_fields() {
+        assert_eq!("1980".parse(), Ok(Year(1980)));
+        assert_eq!(
+            "198".parse::<Year>().unwrap_err().to_string(),
+            "198 is not a four digit year"
+        );
+        assert_eq!(
+            "74in".parse(),
+            Ok(Height {
+                value: 74,
+                unit: HeightUnit::In
+            })
+        );
+        for bad in ["74", "cm", "74ft", "99999999999cm"] {
+            assert_eq!(
+                bad.parse::<Height>(),
+                Err(FieldParseError::new(bad, "a number of cm or in"))
+            );
+        }
+        assert_eq!("#623a2f".parse(), Ok(HexColor([0x62, 0x3a, 0x2f])));
+        assert!("#623A2F".parse::<HexColor>().is_err());
+        assert!("623a2f".parse::<HexColor>().is_err());
+        assert_eq!("hzl".parse(), Ok(EyeColor::Hzl));
+        assert_eq!(
+            "prp".parse::<EyeColor>().unwrap_err().to_string(),
+            "prp is not one of amb, blu, brn, gry, grn, hzl or oth"
+        );
+        let pid: PassportId = "000000001".parse().unwrap();
+        assert_eq!((pid, pid.to_string()), (PassportId(1), "000000001".into()));
+        assert!("0123456789".parse::<PassportId>().is_err());
+    }
+
+    #[test]
+    fn validated_passport() {
+        let passports = generate(VALID);
+        let validated: Vec<ValidatedPassport> = passports
+            .iter()
+            .map(|p| ValidatedPassport::try_from(p).unwrap())
+            .collect();
+        let first = &validated[0];
+        assert_eq!(first.birth_year(), Year(1980));
+        assert_eq!(first.height().unit, HeightUnit::In);
+        assert_eq!(first.hair_color(), HexColor([0x62, 0x3a, 0x2f]));
+        assert_eq!(first.eye_color(), EyeColor::Grn);
+        assert_eq!(first.passport_id().to_string(), "087499704");
+        assert_eq!(first.country_id(), None);
+        assert_eq!(validated[1].country_id(), Some("129"));
+
+        for passport in &generate(INVALID) {
+            let report = ValidatedPassport::try_from(passport).unwrap_err();
+            assert!(!report.is_valid());
+        }
+    }
+
+    fn to_json(passports: &[Passport]) -> String {
+        serde_json::to_string(passports).unwrap()
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let mut input = String::from(VALID);
+        input.push_str("\nhgt:59in eye:blue\n");
+        let passports = generate(&input);
+        let mut out = vec![];
+        write_records(&passports, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with(
+            "byr:1980 iyr:2012 eyr:2030 hgt:74in hcl:#623a2f ecl:grn pid:087499704\n\n"
+        ));
+        assert!(out.ends_with("\n\nhgt:59in eye:blue\n"));
+        assert_eq!(to_json(&generate(&out)), to_json(&passports));
+    }
+
+    #[test]
+    fn json_lines_round_trip() {
+        let mut passports = generate(INVALID);
+        passports.extend(generate("eye:blue pid:1"));
+        let mut out = vec![];
+        write_json_lines(&passports, &mut out).unwrap();
+        let read = read_json_lines(&out[..]).unwrap();
+        assert_eq!(to_json(&read), to_json(&passports));
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("\"cid\":null,\"eye\":\"blue\"}\n"));
+
+        let fixture =
+            "{\"byr\": \"1980\", \"pid\": \"087499704\"}\n\n{\"ecl\": \"grn\", \"eye\": \"a:b\"}\n";
+        let read = read_json_lines(fixture.as_bytes()).unwrap();
+        assert_eq!(read[1].extra()["eye"], "a:b");
+        let mut text = vec![];
+        write_records(&read, &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert_eq!(text, "byr:1980 pid:087499704\n\necl:grn eye:a:b\n");
+        assert_eq!(to_json(&generate(&text)), to_json(&read));
+
+        for (line, why) in [
+            ("{}", "passport 1: has no fields to write"),
+            (
+                "{\"hgt\": \"59 in\"}",
+                "passport 1: hgt value \"59 in\" holds whitespace",
+            ),
+            (
+                "{\"\": \"x\"}",
+                "passport 1: \"\" can't be written as a key",
+            ),
+            (
+                "{\"e:y\": \"x\"}",
+                "passport 1: \"e:y\" can't be written as a key",
+            ),
+            (
+                "{\"e y\": \"x\"}",
+                "passport 1: \"e y\" can't be written as a key",
+            ),
+        ] {
+            let read = read_json_lines(line.as_bytes()).unwrap();
+            let err = write_records(&read, &mut vec![]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(err.to_string(), why);
+        }
+
+        let err = read_json_lines("{}\n{\"byr\": 1980}".as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("line 2: invalid type"));
+    }
+
+    #[test]
+    fn csv() {
+        let passports = generate("byr:1980 hcl:#623a2f\n\nnote:a,\"b\" pid:087499704");
+        let mut out = vec![];
+        write_csv(&passports, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            indoc! {r#"
+                byr,iyr,eyr,hgt,hcl,ecl,pid,cid,note
+                1980,,,,#623a2f,,,,
+                ,,,,,,087499704,,"a,""b"""
+            "#}
+        );
+    }
 }
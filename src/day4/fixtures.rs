@@ -0,0 +1,289 @@
+//! Random passports that come with their expected verdicts, for checking
+//! that validation agrees with the rules on many more cases than the
+//! puzzle's examples.
+
+/// A small PCG-style generator, so fixtures are reproducible from a seed
+/// without pulling in `rand`.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed ^ 0x9e37_79b9_7f4a_7c15)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        let x = self.0;
+        (x ^ (x >> 33)).wrapping_mul(0xff51_afd7_ed55_8ccd) ^ (x >> 29)
+    }
+
+    /// A number in `0..n`. Panics if `n` is zero.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// A number in `lo..=hi`.
+    pub fn between(&mut self, lo: u64, hi: u64) -> u64 {
+        lo + self.below(hi - lo + 1)
+    }
+
+    /// `true` with probability `p`.
+    pub fn chance(&mut self, p: f64) -> bool {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64 > 1.0 - p
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u64) as usize]
+    }
+}
+
+/// How likely each field is to be left out, given a value at the edge of
+/// its valid range, or given a value that is almost but not quite valid.
+/// Otherwise a field gets an ordinary valid value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mix {
+    pub missing: f64,
+    pub boundary: f64,
+    pub near_miss: f64,
+}
+
+impl Default for Mix {
+    /// Roughly half of the passports come out strictly valid.
+    fn default() -> Self {
+        Mix {
+            missing: 0.03,
+            boundary: 0.25,
+            near_miss: 0.06,
+        }
+    }
+}
+
+/// One generated passport and what validation should say about it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fixture {
+    /// In the order they are written, which is shuffled.
+    pub fields: Vec<(&'static str, String)>,
+    /// The puzzle's format, fields split over lines at random.
+    pub text: String,
+    /// Every required field is present (part 1).
+    pub lax: bool,
+    /// Every required field is present and valid (part 2).
+    pub strict: bool,
+}
+
+/// A batch of fixtures, which can be written out as one puzzle input.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Batch(pub Vec<Fixture>);
+
+impl Batch {
+    /// All the passports, separated by blank lines.
+    pub fn text(&self) -> String {
+        let texts: Vec<&str> = self.0.iter().map(|f| f.text.as_str()).collect();
+        texts.join("\n\n")
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Fixture> {
+        self.0.iter()
+    }
+
+    pub fn lax_count(&self) -> u64 {
+        self.0.iter().filter(|f| f.lax).count() as u64
+    }
+
+    pub fn strict_count(&self) -> u64 {
+        self.0.iter().filter(|f| f.strict).count() as u64
+    }
+}
+
+enum Kind {
+    Missing,
+    Boundary,
+    NearMiss,
+    Typical,
+}
+
+const EYE_COLORS: [&str; 7] = ["amb", "blu", "brn", "gry", "grn", "hzl", "oth"];
+
+/// Valid values at the edges of each field's range.
+fn boundary(key: &str) -> &'static [&'static str] {
+    match key {
+        "byr" => &["1920", "2002"],
+        "iyr" => &["2010", "2020"],
+        "eyr" => &["2020", "2030"],
+        "hgt" => &["150cm", "193cm", "59in", "76in"],
+        "hcl" => &["#000000", "#ffffff"],
+        "ecl" => &["amb", "oth"],
+        "pid" => &["000000000", "999999999"],
+        _ => &["1"],
+    }
+}
+
+/// Invalid values that are one step away from a valid one.
+fn near_miss(key: &str) -> &'static [&'static str] {
+    match key {
+        "byr" => &["1919", "2003", "198", "19800"],
+        "iyr" => &["2009", "2021"],
+        "eyr" => &["2019", "2031"],
+        "hgt" => &["149cm", "194cm", "58in", "77in", "190", "170ft"],
+        "hcl" => &["#12345g", "#12345", "123456", "#ABCDEF"],
+        "ecl" => &["zzz", "blue", "gr"],
+        "pid" => &["0123456789", "12345678", "12345678a"],
+        _ => &[],
+    }
+}
+
+/// Generates fixtures from a seed, with the same seed giving the same
+/// fixtures.
+#[derive(Debug, Clone)]
+pub struct FixtureGenerator {
+    rng: Rng,
+    mix: Mix,
+}
+
+impl FixtureGenerator {
+    pub fn new(seed: u64) -> Self {
+        FixtureGenerator {
+            rng: Rng::new(seed),
+            mix: Mix::default(),
+        }
+    }
+
+    pub fn with_mix(mut self, mix: Mix) -> Self {
+        self.mix = mix;
+        self
+    }
+
+    fn typical(&mut self, key: &str) -> String {
+        let rng = &mut self.rng;
+        match key {
+            "byr" => rng.between(1921, 2001).to_string(),
+            "iyr" => rng.between(2011, 2019).to_string(),
+            "eyr" => rng.between(2021, 2029).to_string(),
+            "hgt" if rng.chance(0.5) => format!("{}cm", rng.between(151, 192)),
+            "hgt" => format!("{}in", rng.between(60, 75)),
+            "hcl" => format!("#{:06x}", rng.below(1 << 24)),
+            "ecl" => rng.pick(&EYE_COLORS).to_string(),
+            "pid" => format!("{:09}", rng.below(1_000_000_000)),
+            _ => rng.between(1, 999).to_string(),
+        }
+    }
+
+    fn kind(&mut self, key: &str) -> Kind {
+        let mix = self.mix;
+        if self.rng.chance(mix.missing) {
+            Kind::Missing
+        } else if self.rng.chance(mix.boundary) {
+            Kind::Boundary
+        } else if !near_miss(key).is_empty() && self.rng.chance(mix.near_miss) {
+            Kind::NearMiss
+        } else {
+            Kind::Typical
+        }
+    }
+
+    pub fn fixture(&mut self) -> Fixture {
+        let (mut lax, mut strict) = (true, true);
+        let mut fields = vec![];
+        for key in ["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid", "cid"] {
+            let value = match self.kind(key) {
+                Kind::Missing => {
+                    if key != "cid" {
+                        lax = false;
+                        strict = false;
+                    }
+                    continue;
+                }
+                Kind::Boundary => self.rng.pick(boundary(key)).to_string(),
+                Kind::NearMiss => {
+                    strict = false;
+                    self.rng.pick(near_miss(key)).to_string()
+                }
+                Kind::Typical => self.typical(key),
+            };
+            fields.push((key, value));
+        }
+        for i in (1..fields.len()).rev() {
+            fields.swap(i, self.rng.below(i as u64 + 1) as usize);
+        }
+        let mut text = String::new();
+        for (i, (key, value)) in fields.iter().enumerate() {
+            if i > 0 {
+                text.push(if self.rng.chance(0.3) { '\n' } else { ' ' });
+            }
+            text.push_str(&format!("{}:{}", key, value));
+        }
+        Fixture {
+            fields,
+            text,
+            lax,
+            strict,
+        }
+    }
+
+    pub fn batch(&mut self, n: usize) -> Batch {
+        Batch((0..n).map(|_| self.fixture()).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day4::{generate, part1, part2, ValidatedPassport};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn validation_agrees_with_fixtures() {
+        let batch = FixtureGenerator::new(4).batch(5_000);
+        let passports = generate(&batch.text());
+        assert_eq!(passports.len(), 5_000);
+        for (fixture, passport) in batch.iter().zip(&passports) {
+            assert_eq!(passport.validate(false), fixture.lax, "{}", fixture.text);
+            assert_eq!(passport.validate(true), fixture.strict, "{}", fixture.text);
+            assert_eq!(
+                ValidatedPassport::try_from(passport).is_ok(),
+                fixture.strict,
+                "{}",
+                fixture.text
+            );
+        }
+        assert_eq!(part1(&passports), batch.lax_count());
+        assert_eq!(part2(&passports), batch.strict_count());
+        // Both verdicts should be well represented.
+        let strict = batch.strict_count() as f64 / 5_000.0;
+        assert!((0.3..0.7).contains(&strict), "{}", strict);
+        assert!(batch.lax_count() < 5_000);
+    }
+
+    #[test]
+    fn mixes() {
+        let all_valid = Mix {
+            missing: 0.0,
+            boundary: 0.5,
+            near_miss: 0.0,
+        };
+        let batch = FixtureGenerator::new(1).with_mix(all_valid).batch(200);
+        assert!(batch.iter().all(|f| f.strict && f.fields.len() == 8));
+
+        let all_near_misses = Mix {
+            missing: 0.0,
+            boundary: 0.0,
+            near_miss: 1.0,
+        };
+        let batch = FixtureGenerator::new(1)
+            .with_mix(all_near_misses)
+            .batch(200);
+        assert!(batch.iter().all(|f| f.lax && !f.strict));
+        assert_eq!(part2(&generate(&batch.text())), 0);
+    }
+
+    #[test]
+    fn reproducible() {
+        let a = FixtureGenerator::new(7).batch(10);
+        assert_eq!(a, FixtureGenerator::new(7).batch(10));
+        assert_ne!(a, FixtureGenerator::new(8).batch(10));
+    }
+}
@@ -1,55 +1,367 @@
-use itertools::Itertools;
+use crate::parse_error::ParseError;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
 
-#[derive(Debug)]
+/// How many bits of a boarding pass pick the row and the seat in it. The
+/// puzzle's plane has 128 rows (`F`/`B`) of 8 seats (`L`/`R`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PlaneLayout {
+    row_bits: u32,
+    seat_bits: u32,
+}
+
+impl Default for PlaneLayout {
+    fn default() -> Self {
+        PlaneLayout {
+            row_bits: 7,
+            seat_bits: 3,
+        }
+    }
+}
+
+impl PlaneLayout {
+    /// The most bits a code can have, so that every seat id fits a `u32`.
+    /// A `SeatMap` only stores occupied seats, so it copes with any plane
+    /// up to this size.
+    pub const MAX_BITS: u32 = 31;
+
+    /// The most bits picking the seat in a row, so that a `SeatMap` can
+    /// draw each row on one line.
+    pub const MAX_SEAT_BITS: u32 = 8;
+
+    /// A layout, or `None` if its codes would be longer than `MAX_BITS` or
+    /// its rows wider than `MAX_SEAT_BITS`.
+    pub fn new(row_bits: u32, seat_bits: u32) -> Option<Self> {
+        match row_bits.checked_add(seat_bits) {
+            Some(bits) if bits <= Self::MAX_BITS && seat_bits <= Self::MAX_SEAT_BITS => {
+                Some(PlaneLayout {
+                    row_bits,
+                    seat_bits,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn row_bits(&self) -> u32 {
+        self.row_bits
+    }
+
+    pub fn seat_bits(&self) -> u32 {
+        self.seat_bits
+    }
+
+    pub fn rows(&self) -> u32 {
+        1 << self.row_bits
+    }
+
+    pub fn seats_per_row(&self) -> u32 {
+        1 << self.seat_bits
+    }
+
+    /// One past the highest seat id.
+    pub fn seat_count(&self) -> u32 {
+        1 << (self.row_bits + self.seat_bits)
+    }
+
+    pub fn code_len(&self) -> usize {
+        (self.row_bits + self.seat_bits) as usize
+    }
+
+    pub fn id(&self, row: u32, seat: u32) -> u32 {
+        row << self.seat_bits | seat
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct BoardingPass {
-    row: u8,
-    seat: u8,
+    row: u32,
+    seat: u32,
+    layout: PlaneLayout,
 }
 
 impl BoardingPass {
-    pub fn code_to_bin(code: &str) -> u8 {
-        let mut bin_code = code.replace("F", "0");
-        bin_code = bin_code.replace("B", "1");
-        bin_code = bin_code.replace("R", "1");
-        bin_code = bin_code.replace("L", "0");
-        u8::from_str_radix(&bin_code, 2).unwrap()
+    /// Parses a pass for the puzzle's plane.
+    pub fn parse(code: &str) -> Result<Self, ParseError> {
+        Self::parse_with(code, PlaneLayout::default())
+    }
+
+    pub fn parse_with(code: &str, layout: PlaneLayout) -> Result<Self, ParseError> {
+        let row_bits = layout.row_bits as usize;
+        let (mut row, mut seat) = (0, 0);
+        for (i, c) in code.chars().enumerate() {
+            let expected = if i < row_bits {
+                "`F` or `B`"
+            } else {
+                "`L` or `R`"
+            };
+            match (i < row_bits, c) {
+                _ if i >= layout.code_len() => {
+                    let rest: String = code.chars().skip(i).collect();
+                    return Err(ParseError::unexpected(1, i + 1, rest, "end of line"));
+                }
+                (true, 'F') | (true, 'B') => row = row << 1 | (c == 'B') as u32,
+                (false, 'L') | (false, 'R') => seat = seat << 1 | (c == 'R') as u32,
+                _ => return Err(ParseError::unexpected(1, i + 1, c, expected)),
+            }
+        }
+        let len = code.chars().count();
+        if len < layout.code_len() {
+            return Err(ParseError::missing(
+                1,
+                len + 1,
+                format!("a {} character boarding pass", layout.code_len()),
+            ));
+        }
+        Ok(BoardingPass { row, seat, layout })
     }
 
+    /// Like `parse`, but panics on a bad code.
     pub fn new(code: &str) -> Self {
-        let row_code = &code[0..7];
-        let seat_code = &code[7..];
-        BoardingPass {
-            row: Self::code_to_bin(row_code),
-            seat: Self::code_to_bin(seat_code),
+        Self::parse(code).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// The pass for seat `id` in a plane laid out as `layout`, if it has
+    /// that seat.
+    pub fn from_id(id: u32, layout: PlaneLayout) -> Option<Self> {
+        if id >= layout.seat_count() {
+            return None;
         }
+        Some(BoardingPass {
+            row: id >> layout.seat_bits,
+            seat: id & (layout.seats_per_row() - 1),
+            layout,
+        })
+    }
+
+    /// The code that `parse_with` reads back as this pass.
+    pub fn encode(&self) -> String {
+        let bits = |value: u32, len: u32, zero: char, one: char| {
+            (0..len)
+                .rev()
+                .map(move |bit| if value >> bit & 1 == 1 { one } else { zero })
+        };
+        bits(self.row, self.layout.row_bits, 'F', 'B')
+            .chain(bits(self.seat, self.layout.seat_bits, 'L', 'R'))
+            .collect()
+    }
+
+    pub fn row(&self) -> u32 {
+        self.row
     }
 
-    pub fn id(&self) -> u16 {
-        (self.row as u16) * 8 + self.seat as u16
+    pub fn seat(&self) -> u32 {
+        self.seat
+    }
+
+    pub fn layout(&self) -> PlaneLayout {
+        self.layout
+    }
+
+    pub fn id(&self) -> u32 {
+        self.layout.id(self.row, self.seat)
+    }
+}
+
+impl From<u16> for BoardingPass {
+    /// The pass for seat `id` in the puzzle's plane, given more rows if the
+    /// id needs them.
+    fn from(id: u16) -> Self {
+        let mut layout = PlaneLayout::default();
+        while u32::from(id) >= layout.seat_count() {
+            layout = PlaneLayout::new(layout.row_bits + 1, layout.seat_bits).unwrap();
+        }
+        Self::from_id(id.into(), layout).unwrap()
     }
 }
 
+impl FromStr for BoardingPass {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+pub fn try_generate_with(
+    input: &str,
+    layout: PlaneLayout,
+) -> Result<Vec<BoardingPass>, ParseError> {
+    input
+        .lines()
+        .enumerate()
+        .map(|(i, s)| BoardingPass::parse_with(s, layout).map_err(|e| e.offset(i + 1, 1)))
+        .collect()
+}
+
+pub fn try_generate(input: &str) -> Result<Vec<BoardingPass>, ParseError> {
+    try_generate_with(input, PlaneLayout::default())
+}
+
 #[aoc_generator(day5)]
 pub fn generate(input: &str) -> Vec<BoardingPass> {
-    input.lines().map(|s| BoardingPass::new(s)).collect()
+    try_generate(input).unwrap_or_else(|e| panic!("{}", e))
 }
 
 #[aoc(day5, part1)]
-pub fn part1(passes: &[BoardingPass]) -> u16 {
-    passes.iter().map(|b| b.id()).max().unwrap()
+pub fn part1(passes: &[BoardingPass]) -> Option<u32> {
+    passes.iter().map(|b| b.id()).max()
 }
 
-#[aoc(day5, part2)]
-pub fn part2(passes: &[BoardingPass]) -> u16 {
-    let mut last_id = 0u16;
-    for id in passes.iter().map(|b| b.id()).sorted() {
-        if last_id == 0 || last_id + 1 == id {
-            last_id = id;
-        } else {
-            break;
+/// Which seats of a plane have boarding passes, and how many. Only the
+/// occupied seats are stored, so a map costs the same whatever the size of
+/// the plane.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeatMap {
+    layout: PlaneLayout,
+    passes: BTreeMap<u32, u32>,
+}
+
+impl SeatMap {
+    /// A map of the smallest layout that every pass fits in, the puzzle's
+    /// plane if there are none. `None` if the passes' layouts are too
+    /// different for one plane to hold them all.
+    pub fn new(passes: &[BoardingPass]) -> Option<Self> {
+        let layout = passes
+            .iter()
+            .map(BoardingPass::layout)
+            .try_fold(PlaneLayout::default(), |a, b| {
+                PlaneLayout::new(a.row_bits.max(b.row_bits), a.seat_bits.max(b.seat_bits))
+            })?;
+        let mut map = SeatMap {
+            layout,
+            passes: BTreeMap::new(),
+        };
+        for pass in passes {
+            *map.passes
+                .entry(layout.id(pass.row(), pass.seat()))
+                .or_insert(0) += 1;
+        }
+        Some(map)
+    }
+
+    pub fn layout(&self) -> PlaneLayout {
+        self.layout
+    }
+
+    /// How many boarding passes there are for seat `id`.
+    pub fn passes(&self, id: u32) -> u32 {
+        self.passes.get(&id).copied().unwrap_or(0)
+    }
+
+    pub fn is_occupied(&self, id: u32) -> bool {
+        self.passes(id) > 0
+    }
+
+    fn row_of(&self, id: u32) -> u32 {
+        id >> self.layout.seat_bits
+    }
+
+    /// Rows before the first one with anyone in, which the plane doesn't
+    /// really have. All of them if the plane is empty.
+    pub fn missing_front_rows(&self) -> Range<u32> {
+        match self.passes.keys().next() {
+            Some(first) => 0..self.row_of(*first),
+            None => 0..self.layout.rows(),
         }
     }
-    last_id + 1
+
+    /// Rows after the last one with anyone in. None if the plane is empty,
+    /// since the front rows already cover it.
+    pub fn missing_back_rows(&self) -> Range<u32> {
+        let rows = self.layout.rows();
+        match self.passes.keys().next_back() {
+            Some(last) => self.row_of(*last) + 1..rows,
+            None => rows..rows,
+        }
+    }
+
+    /// Every empty seat id, leaving out the rows the plane doesn't have.
+    pub fn missing_seats(&self) -> impl Iterator<Item = u32> + '_ {
+        let seats = self.layout.seats_per_row();
+        let first = self.missing_front_rows().end * seats;
+        let end = self.missing_back_rows().start * seats;
+        let mut from = first;
+        self.passes
+            .keys()
+            .map(Some)
+            .chain(std::iter::once(None))
+            .flat_map(move |id| {
+                let to = id.copied().unwrap_or(end);
+                let empty = from..to;
+                from = to + 1;
+                empty
+            })
+    }
+
+    /// Empty seats with both neighbouring ids occupied.
+    pub fn gaps(&self) -> impl Iterator<Item = u32> + '_ {
+        self.passes
+            .keys()
+            .zip(self.passes.keys().skip(1))
+            .filter(|(a, b)| **b == **a + 2)
+            .map(|(a, _)| a + 1)
+    }
+
+    /// Seat ids with more than one boarding pass, and how many they have.
+    pub fn duplicates(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.passes
+            .iter()
+            .map(|(id, passes)| (*id, *passes))
+            .filter(|(_, passes)| *passes > 1)
+    }
+}
+
+impl fmt::Display for SeatMap {
+    /// One line per row with anyone in: `#` for an occupied seat, `.` for an
+    /// empty one and `!` for a seat with several passes. A run of rows with
+    /// nobody in takes one line, labelled with the first and last row, and
+    /// is left blank for rows the plane doesn't have. So the output grows
+    /// with the number of passes, not the size of the plane.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = (self.layout.rows() - 1).to_string().len();
+        let label = |rows: &Range<u32>| match rows.len() {
+            1 => format!("{:>width$}", rows.start, width = width),
+            _ => format!("{:>width$}-{}", rows.start, rows.end - 1, width = width),
+        };
+        let seats_per_row = self.layout.seats_per_row();
+        let empty_row = ".".repeat(seats_per_row as usize);
+
+        let front = self.missing_front_rows();
+        if !front.is_empty() {
+            writeln!(f, "{}", label(&front))?;
+        }
+        let mut next = front.end;
+        let occupied = self.passes.keys().map(|id| self.row_of(*id));
+        for row in occupied {
+            if row < next {
+                continue;
+            }
+            if row > next {
+                writeln!(f, "{} {}", label(&(next..row)), empty_row)?;
+            }
+            let seats: String = (0..seats_per_row)
+                .map(|seat| match self.passes(self.layout.id(row, seat)) {
+                    0 => '.',
+                    1 => '#',
+                    _ => '!',
+                })
+                .collect();
+            writeln!(f, "{} {}", label(&(row..row + 1)), seats)?;
+            next = row + 1;
+        }
+        let back = self.missing_back_rows();
+        if !back.is_empty() {
+            writeln!(f, "{}", label(&back))?;
+        }
+        Ok(())
+    }
+}
+
+#[aoc(day5, part2)]
+pub fn part2(passes: &[BoardingPass]) -> Option<u32> {
+    SeatMap::new(passes)?.gaps().next()
 }
 
 #[cfg(test)]
@@ -66,5 +378,157 @@ mod tests {
         assert_eq!(pass.row, 14);
         assert_eq!(pass.seat, 7);
         assert_eq!(pass.id(), 119);
+
+        assert_eq!(part1(&passes(&[119, 567])), Some(567));
+        assert_eq!(part1(&[]), None);
+    }
+
+    #[test]
+    fn generator_errors() {
+        assert_eq!(
+            try_generate("BFFFBBFRRR\nFFFBBBFRLX").unwrap_err(),
+            ParseError::unexpected(2, 10, "X", "`L` or `R`")
+        );
+        assert_eq!(
+            try_generate("BFFFBBRRRR").unwrap_err(),
+            ParseError::unexpected(1, 7, "R", "`F` or `B`")
+        );
+        assert_eq!(
+            try_generate("BFFFBBFRR").unwrap_err(),
+            ParseError::missing(1, 10, "a 10 character boarding pass")
+        );
+        assert_eq!(
+            try_generate("BFFFBBFRRRL").unwrap_err(),
+            ParseError::unexpected(1, 11, "L", "end of line")
+        );
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        for code in ["BFFFBBFRRR", "FFFFFFFLLL", "BBBBBBBRRR"] {
+            let pass = BoardingPass::parse(code).unwrap();
+            assert_eq!(pass.encode(), code);
+            assert_eq!(BoardingPass::from(pass.id() as u16), pass);
+        }
+        assert_eq!(BoardingPass::from(567).encode(), "BFFFBBFRRR");
+        assert_eq!("FFFBBBFRRR".parse::<BoardingPass>().unwrap().id(), 119);
+
+        let big = BoardingPass::from(1024);
+        assert_eq!(big.layout().row_bits(), 8);
+        assert_eq!((big.row(), big.seat()), (128, 0));
+        assert_eq!(big.encode(), "BFFFFFFFLLL");
+    }
+
+    #[test]
+    fn plane_layouts() {
+        let layout = PlaneLayout::new(9, 4).unwrap();
+        assert_eq!((layout.rows(), layout.seats_per_row()), (512, 16));
+        let pass = BoardingPass::parse_with("BFFFFFFFBRLLR", layout).unwrap();
+        assert_eq!((pass.row(), pass.seat()), (257, 9));
+        assert_eq!(pass.id(), 257 * 16 + 9);
+        assert_eq!(BoardingPass::from_id(pass.id(), layout), Some(pass));
+        assert_eq!(BoardingPass::from_id(512 * 16, layout), None);
+        assert_eq!(
+            BoardingPass::parse_with("BFFFBBFRRR", layout).unwrap_err(),
+            ParseError::unexpected(1, 8, "R", "`F` or `B`")
+        );
+        assert_eq!(
+            try_generate_with("BFFFFFFFBRLLR\nBFFF", layout).unwrap_err(),
+            ParseError::missing(2, 5, "a 13 character boarding pass")
+        );
+    }
+
+    #[test]
+    fn plane_layout_limits() {
+        assert_eq!(PlaneLayout::new(16, 16), None);
+        assert_eq!(PlaneLayout::new(u32::MAX, 1), None);
+        assert_eq!(PlaneLayout::new(1, 32), None);
+
+        let long = PlaneLayout::new(28, 3).unwrap();
+        assert_eq!(long.seat_count(), 1 << 31);
+        let code = "B".repeat(28) + "RRR";
+        let last = BoardingPass::parse_with(&code, long).unwrap();
+        assert_eq!(last.id(), u32::MAX >> 1);
+        assert_eq!(last.encode(), code);
+        assert_eq!(BoardingPass::from_id(u32::MAX, long), None);
+
+        assert_eq!(PlaneLayout::new(0, 9), None);
+        let wide = PlaneLayout::new(23, 8).unwrap();
+        let pass = BoardingPass::from_id(5, wide).unwrap();
+        assert_eq!((pass.row(), pass.seat()), (0, 5));
+
+        let ids = [0, 2, 2, 9, u32::MAX >> 1];
+        let passes: Vec<_> = ids
+            .iter()
+            .map(|id| BoardingPass::from_id(*id, long).unwrap())
+            .collect();
+        let map = SeatMap::new(&passes).unwrap();
+        assert_eq!(map.layout(), long);
+        assert_eq!(map.missing_front_rows(), 0..0);
+        assert_eq!(map.missing_back_rows(), 1 << 28..1 << 28);
+        let missing: Vec<_> = map.missing_seats().take(4).collect();
+        assert_eq!(missing, [1, 3, 4, 5]);
+        assert_eq!(map.missing_seats().last(), Some((u32::MAX >> 1) - 1));
+        assert_eq!(map.duplicates().collect::<Vec<_>>(), [(2, 2)]);
+        assert_eq!(map.to_string().lines().count(), 4);
+        assert_eq!(part2(&passes), Some(1));
+
+        let mixed = [pass, last];
+        assert_eq!(SeatMap::new(&mixed), None);
+        assert_eq!(part2(&mixed), None);
+    }
+
+    #[test]
+    fn parse_never_panics() {
+        for code in [
+            "",
+            "B",
+            "BFFFBBF",
+            "BFFFBBFRRRR",
+            "bfffbbfrrr",
+            "BFFFBBFRR\u{e9}",
+        ] {
+            assert!(BoardingPass::parse(code).is_err(), "{:?}", code);
+        }
+    }
+
+    fn passes(ids: &[u16]) -> Vec<BoardingPass> {
+        ids.iter().map(|id| BoardingPass::from(*id)).collect()
+    }
+
+    #[test]
+    fn part2_from_seat_zero() {
+        assert_eq!(part2(&passes(&[0, 1, 3, 4])), Some(2));
+        assert_eq!(part2(&passes(&[9, 10, 12, 8])), Some(11));
+        assert_eq!(part2(&passes(&[8, 9])), None);
+    }
+
+    #[test]
+    fn seat_map() {
+        let map = SeatMap::new(&passes(&[17, 19, 20, 22, 24, 25, 25, 34])).unwrap();
+        assert_eq!(map.missing_front_rows(), 0..2);
+        assert_eq!(map.missing_back_rows(), 5..128);
+        let missing: Vec<u32> = map.missing_seats().collect();
+        assert_eq!(
+            missing,
+            [16, 18, 21, 23, 26, 27, 28, 29, 30, 31, 32, 33, 35, 36, 37, 38, 39]
+        );
+        assert_eq!(map.gaps().collect::<Vec<_>>(), [18, 21, 23]);
+        assert_eq!(map.duplicates().collect::<Vec<_>>(), [(25, 2)]);
+        assert_eq!(
+            map.to_string(),
+            "  0-1\n  2 .#.##.#.\n  3 #!......\n  4 ..#.....\n  5-127\n"
+        );
+        let spread = SeatMap::new(&passes(&[8, 42, 1023])).unwrap();
+        assert_eq!(
+            spread.to_string(),
+            "  0\n  1 #.......\n  2-4 ........\n  5 ..#.....\n  6-126 ........\n127 .......#\n"
+        );
+
+        let empty = SeatMap::new(&[]).unwrap();
+        assert_eq!(empty.missing_front_rows(), 0..128);
+        assert_eq!(empty.missing_back_rows(), 128..128);
+        assert_eq!(empty.missing_seats().count(), 0);
+        assert_eq!(empty.to_string(), "  0-127\n");
     }
 }
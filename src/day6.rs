@@ -1,8 +1,153 @@
-use std::collections::HashSet;
+use crate::record_parser::{RecordFactory, RecordParser};
+use std::collections::{HashMap, HashSet};
+
+/// The answers of one person. Answers `a` to `z` are kept as a bitmask,
+/// anything else falls back to a `HashSet`.
+#[derive(Debug, Clone)]
+pub enum AnswerSet {
+    Bits(u32),
+    Chars(HashSet<char>),
+}
+
+impl Default for AnswerSet {
+    fn default() -> Self {
+        AnswerSet::Bits(0)
+    }
+}
+
+fn bit(c: char) -> Option<u32> {
+    match c {
+        'a'..='z' => Some(1 << (c as u32 - 'a' as u32)),
+        _ => None,
+    }
+}
+
+impl AnswerSet {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn insert(&mut self, c: char) {
+        match (&mut *self, bit(c)) {
+            (AnswerSet::Bits(bits), Some(b)) => *bits |= b,
+            (AnswerSet::Chars(chars), _) => {
+                chars.insert(c);
+            }
+            (AnswerSet::Bits(_), None) => {
+                let mut chars = self.to_hash_set();
+                chars.insert(c);
+                *self = AnswerSet::Chars(chars);
+            }
+        }
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        match self {
+            AnswerSet::Bits(bits) => bit(c).is_some_and(|b| bits & b != 0),
+            AnswerSet::Chars(chars) => chars.contains(&c),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            AnswerSet::Bits(bits) => bits.count_ones() as usize,
+            AnswerSet::Chars(chars) => chars.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = char> + '_ {
+        let bits = match self {
+            AnswerSet::Bits(bits) => *bits,
+            AnswerSet::Chars(_) => 0,
+        };
+        let chars = match self {
+            AnswerSet::Bits(_) => None,
+            AnswerSet::Chars(chars) => Some(chars.iter().cloned()),
+        };
+        ('a'..='z')
+            .filter(move |c| bits & bit(*c).unwrap() != 0)
+            .chain(chars.into_iter().flatten())
+    }
+
+    pub fn to_hash_set(&self) -> HashSet<char> {
+        self.iter().collect()
+    }
+
+    fn combine(
+        &self,
+        other: &AnswerSet,
+        bits: fn(u32, u32) -> u32,
+        chars: fn(&HashSet<char>, &HashSet<char>) -> HashSet<char>,
+    ) -> AnswerSet {
+        match (self, other) {
+            (AnswerSet::Bits(a), AnswerSet::Bits(b)) => AnswerSet::Bits(bits(*a, *b)),
+            _ => AnswerSet::Chars(chars(&self.to_hash_set(), &other.to_hash_set())),
+        }
+    }
+
+    pub fn union(&self, other: &AnswerSet) -> AnswerSet {
+        self.combine(other, |a, b| a | b, |a, b| a | b)
+    }
+
+    pub fn intersection(&self, other: &AnswerSet) -> AnswerSet {
+        self.combine(other, |a, b| a & b, |a, b| a & b)
+    }
+
+    pub fn symmetric_difference(&self, other: &AnswerSet) -> AnswerSet {
+        self.combine(other, |a, b| a ^ b, |a, b| a ^ b)
+    }
+}
+
+impl PartialEq for AnswerSet {
+    fn eq(&self, other: &AnswerSet) -> bool {
+        match (self, other) {
+            (AnswerSet::Bits(a), AnswerSet::Bits(b)) => a == b,
+            _ => self.to_hash_set() == other.to_hash_set(),
+        }
+    }
+}
+
+impl Eq for AnswerSet {}
+
+impl std::iter::FromIterator<char> for AnswerSet {
+    fn from_iter<I: IntoIterator<Item = char>>(iter: I) -> Self {
+        let mut set = AnswerSet::new();
+        for c in iter {
+            set.insert(c);
+        }
+        set
+    }
+}
+
+impl From<HashSet<char>> for AnswerSet {
+    fn from(chars: HashSet<char>) -> Self {
+        chars.into_iter().collect()
+    }
+}
 
 #[derive(Debug, Default)]
 pub struct Group {
-    answers: Vec<HashSet<char>>,
+    answers: Vec<AnswerSet>,
+}
+
+impl From<Vec<AnswerSet>> for Group {
+    fn from(answers: Vec<AnswerSet>) -> Self {
+        Group { answers }
+    }
+}
+
+impl From<Vec<HashSet<char>>> for Group {
+    fn from(answers: Vec<HashSet<char>>) -> Self {
+        answers
+            .into_iter()
+            .map(AnswerSet::from)
+            .collect::<Vec<_>>()
+            .into()
+    }
 }
 
 impl Group {
@@ -10,49 +155,73 @@ impl Group {
         Default::default()
     }
 
-    pub fn append(&mut self, answer: HashSet<char>) {
-        self.answers.push(answer);
+    pub fn append(&mut self, answer: impl Into<AnswerSet>) {
+        self.answers.push(answer.into());
+    }
+
+    // Answers given by anyone in the group.
+    pub fn union(&self) -> AnswerSet {
+        self.answers
+            .iter()
+            .fold(AnswerSet::new(), |any, a| any.union(a))
+    }
+
+    // Answers given by everyone in the group, none for an empty group.
+    pub fn intersection(&self) -> AnswerSet {
+        let mut answers = self.answers.iter();
+        let first = answers.next().cloned().unwrap_or_default();
+        answers.fold(first, |shared, a| shared.intersection(a))
+    }
+
+    // Answers given by an odd number of people in the group.
+    pub fn symmetric_difference(&self) -> AnswerSet {
+        self.answers
+            .iter()
+            .fold(AnswerSet::new(), |odd, a| odd.symmetric_difference(a))
+    }
+
+    // Answers given by at least `k` people in the group.
+    pub fn answered_by_at_least(&self, k: usize) -> AnswerSet {
+        let mut counts = HashMap::new();
+        for c in self.answers.iter().flat_map(AnswerSet::iter) {
+            *counts.entry(c).or_insert(0) += 1;
+        }
+        counts
+            .into_iter()
+            .filter(|(_, count)| *count >= k)
+            .map(|(c, _)| c)
+            .collect()
     }
 
     // Count of any unique answer.
     pub fn count_unique(&self) -> u16 {
-        let mut group = self.answers.first().unwrap().clone();
-        for a in &self.answers[1..] {
-            group.extend(a);
-        }
-        group.len() as u16
+        self.union().len() as u16
     }
 
     // Count of shared answers.
     pub fn count_shared(&self) -> u16 {
-        let mut group = self.answers.first().unwrap().clone();
-        for a in &self.answers[1..] {
-            let g = group.intersection(a);
-            group = g.cloned().collect();
-        }
-        group.len() as u16
+        self.intersection().len() as u16
+    }
+}
+
+/// Reads each line of a record as one person's `AnswerSet`, without going
+/// through a `HashSet` first.
+pub struct GroupFactory;
+
+impl RecordFactory<Group> for GroupFactory {
+    fn new_record(&self) -> Group {
+        Group::new()
+    }
+
+    fn accept_field(&self, record: &mut Group, field: &str) {
+        record.append(field.chars().collect::<AnswerSet>());
     }
 }
 
 #[aoc_generator(day6)]
 pub fn generate(input: &str) -> Vec<Group> {
-    let mut group = Group::new();
-    let mut output = vec![];
-    for s in input.lines() {
-        // Empty line means end of group.
-        if s.is_empty() {
-            output.push(group);
-            group = Group::new();
-        } else {
-            let mut individual = HashSet::new();
-            for c in s.chars() {
-                individual.insert(c);
-            }
-            group.append(individual);
-        }
-    }
-    output.push(group);
-    output
+    let parser: RecordParser = Default::default();
+    parser.parse_iter(GroupFactory, input).collect()
 }
 
 #[aoc(day6, part1)]
@@ -64,3 +233,64 @@ pub fn part1(inputs: &[Group]) -> u16 {
 pub fn part2(inputs: &[Group]) -> u16 {
     inputs.iter().map(|i| i.count_shared()).sum()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(s: &str) -> AnswerSet {
+        s.chars().collect()
+    }
+
+    #[test]
+    fn example() {
+        let groups = generate("abc\n\na\nb\nc\n\nab\nac\n\na\na\na\na\n\nb\n");
+        assert_eq!(groups.len(), 5);
+        assert_eq!(part1(&groups), 11);
+        assert_eq!(part2(&groups), 6);
+
+        let groups = generate("ab\nbé\n");
+        assert_eq!(groups[0].answers, [AnswerSet::Bits(0b11), set("bé")]);
+    }
+
+    #[test]
+    fn set_algebra() {
+        let group = Group::from(vec![set("abc"), set("bcd"), set("cde")]);
+        assert_eq!(group.union(), set("abcde"));
+        assert_eq!(group.intersection(), set("c"));
+        assert_eq!(group.symmetric_difference(), set("ace"));
+        assert_eq!(group.answered_by_at_least(2), set("bcd"));
+        assert_eq!(group.answered_by_at_least(3), group.intersection());
+        assert_eq!(group.answered_by_at_least(1), group.union());
+    }
+
+    #[test]
+    fn empty_group() {
+        let group = Group::new();
+        assert_eq!(group.count_unique(), 0);
+        assert_eq!(group.count_shared(), 0);
+        assert!(group.symmetric_difference().is_empty());
+        assert!(group.answered_by_at_least(0).is_empty());
+    }
+
+    #[test]
+    fn answer_set_backends() {
+        assert_eq!(
+            set("abz"),
+            AnswerSet::Bits(0b10_0000_0000_0000_0000_0000_0011)
+        );
+        let wide = set("aéb");
+        assert!(matches!(wide, AnswerSet::Chars(_)));
+        assert_eq!(wide.len(), 3);
+        assert!(wide.contains('é') && wide.contains('a') && !wide.contains('z'));
+
+        assert_eq!(set("abc").intersection(&wide), set("ab"));
+        assert_eq!(set("abc").union(&wide), set("abcé"));
+        assert_eq!(wide.symmetric_difference(&set("bc")), set("aéc"));
+
+        let group = Group::from(vec![set("aé"), set("éb"), set("é")]);
+        assert_eq!(group.count_shared(), 1);
+        assert_eq!(group.count_unique(), 3);
+        assert_eq!(group.answered_by_at_least(2), set("é"));
+    }
+}
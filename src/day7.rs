@@ -1,4 +1,4 @@
-use crate::record_parser::{RecordFactory, RecordParser};
+use crate::record_parser::{RecordFactory, RecordParser, Separator};
 use std::collections::{HashMap, HashSet};
 
 struct RuleBookFactory;
@@ -64,28 +64,49 @@ impl BagRuleBook {
 }
 
 #[aoc_generator(day7)]
-pub fn generate(input: &str) -> Vec<BagRuleBook> {
-    let mut parser = RecordParser {
-        // This is actually any string that doesn't appear in the input to mean
-        // take the entire input as a single record.
-        rec_sep: "<EOF>".into(),
-        field_sep: "\n".into(),
+pub fn generate(input: &str) -> BagRuleBook {
+    let parser = RecordParser {
+        rec_sep: Separator::WholeInput,
+        field_sep: Separator::EachLine,
+        ..Default::default()
     };
-    parser.parse(RuleBookFactory, input)
+    parser
+        .parse(RuleBookFactory, input)
+        .pop()
+        .unwrap_or_default()
 }
 
 #[aoc(day7, part1)]
-pub fn part1(rules: &[BagRuleBook]) -> u64 {
-    if let Some(rule_book) = rules.first() {
-        return rule_book.count_outer_bags("shiny gold");
-    }
-    unreachable!();
+pub fn part1(rule_book: &BagRuleBook) -> u64 {
+    rule_book.count_outer_bags("shiny gold")
 }
 
 #[aoc(day7, part2)]
-pub fn part2(rules: &[BagRuleBook]) -> u64 {
-    if let Some(rule_book) = rules.first() {
-        return rule_book.count_inner_bags("shiny gold");
+pub fn part2(rule_book: &BagRuleBook) -> u64 {
+    rule_book.count_inner_bags("shiny gold")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn example() {
+        let input = indoc! {"
+            light red bags contain 1 bright white bag, 2 muted yellow bags.
+            dark orange bags contain 3 bright white bags, 4 muted yellow bags.
+            bright white bags contain 1 shiny gold bag.
+            muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
+            shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.
+            dark olive bags contain 3 faded blue bags, 4 dotted black bags.
+            vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
+            faded blue bags contain no other bags.
+            dotted black bags contain no other bags.
+
+        "};
+        let rule_book = generate(input);
+        assert_eq!(part1(&rule_book), 4);
+        assert_eq!(part2(&rule_book), 32);
     }
-    unreachable!();
 }
@@ -1,4 +1,5 @@
-use crate::record_parser::{RecordFactory, RecordParser};
+use crate::parse_error::{column_of, ParseError};
+use crate::record_parser::{RecordFactory, RecordParser, Separator};
 use std::collections::HashSet;
 
 struct ProgFactory;
@@ -9,11 +10,32 @@ impl RecordFactory<(String, i64)> for ProgFactory {
     }
 
     fn accept_field(&self, statement: &mut (String, i64), field: &str) {
-        let parts: Vec<&str> = field.split(' ').collect();
-        let instruction = parts[0];
-        let arg = parts[1].parse::<i64>().unwrap();
+        self.try_accept_field(statement, field)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_accept_field(
+        &self,
+        statement: &mut (String, i64),
+        field: &str,
+    ) -> Result<(), ParseError> {
+        let (instruction, arg) = field.split_once(' ').ok_or_else(|| {
+            ParseError::missing(1, field.chars().count() + 1, "an instruction argument")
+        })?;
+        if !matches!(instruction, "acc" | "jmp" | "nop") {
+            return Err(ParseError::unexpected(
+                1,
+                1,
+                instruction,
+                "`acc`, `jmp` or `nop`",
+            ));
+        }
+        let arg = arg.parse::<i64>().map_err(|_| {
+            ParseError::unexpected(1, column_of(field, arg), arg, "a signed number")
+        })?;
         statement.0 = instruction.into();
         statement.1 = arg;
+        Ok(())
     }
 }
 
@@ -62,7 +84,7 @@ pub fn eval(program: &[(String, i64)], do_fix: bool) -> i64 {
                 _ => (),
             }
             if !do_jump {
-                ip += 1 as usize;
+                ip += 1;
             }
             if ip >= program.len() {
                 break;
@@ -73,20 +95,25 @@ pub fn eval(program: &[(String, i64)], do_fix: bool) -> i64 {
         }
         tweak_next += 1;
         if tweak_next >= tweak_idxs.len() {
-            println!("Couldn't find a tweak allowing normal exit.");
+            aoc_warn!("Couldn't find a tweak allowing normal exit.");
             break;
         }
     }
     acc
 }
 
+pub fn try_generate(input: &str) -> Result<Vec<(String, i64)>, ParseError> {
+    let parser = RecordParser {
+        rec_sep: Separator::EachLine,
+        field_sep: Separator::EachLine,
+        ..Default::default()
+    };
+    parser.try_parse(ProgFactory, input)
+}
+
 #[aoc_generator(day8)]
 pub fn generate(input: &str) -> Vec<(String, i64)> {
-    let mut parser = RecordParser {
-        rec_sep: "\n".into(),
-        field_sep: "\n".into(),
-    };
-    parser.parse(ProgFactory, input)
+    try_generate(input).unwrap_or_else(|e| panic!("{}", e))
 }
 
 #[aoc(day8, part1)]
@@ -123,4 +150,20 @@ mod test {
             8
         )
     }
+
+    #[test]
+    fn generator_errors() {
+        assert_eq!(
+            try_generate("nop +0\nacc +1\njump +4\n").unwrap_err(),
+            ParseError::unexpected(3, 1, "jump", "`acc`, `jmp` or `nop`")
+        );
+        assert_eq!(
+            try_generate("nop +0\nacc 1x\n").unwrap_err(),
+            ParseError::unexpected(2, 5, "1x", "a signed number")
+        );
+        assert_eq!(
+            try_generate("nop\n").unwrap_err(),
+            ParseError::missing(1, 4, "an instruction argument")
+        );
+    }
 }
@@ -0,0 +1,357 @@
+use crate::parse_error::{column_of, ParseError};
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{Index, IndexMut};
+use std::str::FromStr;
+
+/// What a position outside the grid refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Edges {
+    /// Nothing, lookups return `None`.
+    #[default]
+    Bounded,
+    /// Columns repeat forever to the left and right, rows are bounded.
+    WrapX,
+    /// Both columns and rows repeat, like a torus.
+    WrapBoth,
+    /// The nearest cell on the edge.
+    Clamp,
+}
+
+/// A rectangle of cells stored row by row. Positions are `(x, y)` with the
+/// origin at the top left.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grid<T> {
+    cells: Vec<T>,
+    width: usize,
+    height: usize,
+    edges: Edges,
+}
+
+const NEIGHBOURS4: [(isize, isize); 4] = [(0, -1), (-1, 0), (1, 0), (0, 1)];
+const NEIGHBOURS8: [(isize, isize); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+impl<T> Grid<T> {
+    /// A bounded grid from `cells` in row order. Panics if there are not
+    /// exactly `width * height` of them.
+    pub fn new(width: usize, height: usize, cells: Vec<T>) -> Self {
+        assert_eq!(
+            cells.len(),
+            width * height,
+            "a {}x{} grid needs {} cells",
+            width,
+            height,
+            width * height
+        );
+        Grid {
+            cells,
+            width,
+            height,
+            edges: Edges::Bounded,
+        }
+    }
+
+    pub fn filled(width: usize, height: usize, value: T) -> Self
+    where
+        T: Clone,
+    {
+        Grid::new(width, height, vec![value; width * height])
+    }
+
+    pub fn with_edges(mut self, edges: Edges) -> Self {
+        self.edges = edges;
+        self
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn edges(&self) -> Edges {
+        self.edges
+    }
+
+    /// Where `(x, y)` lands once the edge behaviour is applied, or `None` if
+    /// it is off a bounded edge.
+    pub fn resolve(&self, x: isize, y: isize) -> Option<(usize, usize)> {
+        if self.cells.is_empty() {
+            return None;
+        }
+        let bounded = |v: isize, len: usize| usize::try_from(v).ok().filter(|v| *v < len);
+        let wrapped = |v: isize, len: usize| v.rem_euclid(len as isize) as usize;
+        let clamped = |v: isize, len: usize| v.clamp(0, len as isize - 1) as usize;
+        let (w, h) = (self.width, self.height);
+        match self.edges {
+            Edges::Bounded => Some((bounded(x, w)?, bounded(y, h)?)),
+            Edges::WrapX => Some((wrapped(x, w), bounded(y, h)?)),
+            Edges::WrapBoth => Some((wrapped(x, w), wrapped(y, h))),
+            Edges::Clamp => Some((clamped(x, w), clamped(y, h))),
+        }
+    }
+
+    fn offset(&self, (x, y): (usize, usize)) -> Option<usize> {
+        let (x, y) = self.resolve(isize::try_from(x).ok()?, isize::try_from(y).ok()?)?;
+        Some(x + y * self.width)
+    }
+
+    pub fn get(&self, pos: (usize, usize)) -> Option<&T> {
+        self.offset(pos).map(|i| &self.cells[i])
+    }
+
+    pub fn get_mut(&mut self, pos: (usize, usize)) -> Option<&mut T> {
+        self.offset(pos).map(move |i| &mut self.cells[i])
+    }
+
+    /// Every cell with its position, row by row.
+    pub fn iter(&self) -> impl Iterator<Item = ((usize, usize), &T)> {
+        let width = self.width;
+        self.cells
+            .iter()
+            .enumerate()
+            .map(move |(i, c)| ((i % width, i / width), c))
+    }
+
+    pub fn row(&self, y: usize) -> Option<&[T]> {
+        let start = y.checked_mul(self.width)?;
+        self.cells
+            .get(start..start + self.width)
+            .filter(|_| y < self.height)
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        (0..self.height).map(move |y| self.row(y).unwrap())
+    }
+
+    /// The cells of column `x` from top to bottom, empty past the right edge.
+    pub fn column(&self, x: usize) -> impl Iterator<Item = &T> {
+        let cells = if x < self.width {
+            &self.cells[x..]
+        } else {
+            &[]
+        };
+        cells.iter().step_by(self.width.max(1))
+    }
+
+    pub fn columns(&self) -> impl Iterator<Item = impl Iterator<Item = &T>> {
+        (0..self.width).map(move |x| self.column(x))
+    }
+
+    /// The up to four cells sharing an edge with `pos`, after applying the
+    /// edge behaviour. Offsets that land back on `pos` are skipped, but on
+    /// grids narrower than three a wrapped neighbour may appear twice.
+    pub fn neighbours4(&self, pos: (usize, usize)) -> impl Iterator<Item = ((usize, usize), &T)> {
+        self.neighbours(pos, &NEIGHBOURS4)
+    }
+
+    /// Like `neighbours4`, but diagonals are included too.
+    pub fn neighbours8(&self, pos: (usize, usize)) -> impl Iterator<Item = ((usize, usize), &T)> {
+        self.neighbours(pos, &NEIGHBOURS8)
+    }
+
+    fn neighbours<'a>(
+        &'a self,
+        pos: (usize, usize),
+        offsets: &'static [(isize, isize)],
+    ) -> impl Iterator<Item = ((usize, usize), &'a T)> + 'a {
+        let (x, y) = (pos.0 as isize, pos.1 as isize);
+        offsets
+            .iter()
+            .filter_map(move |(dx, dy)| self.resolve(x + dx, y + dy))
+            .filter(move |p| *p != pos)
+            .map(move |p| (p, &self.cells[p.0 + p.1 * self.width]))
+    }
+
+    /// Parses one row per line, mapping each char with `cell`. Lines are
+    /// trimmed and must all be as wide as the first.
+    pub fn parse_with<E: fmt::Display>(
+        input: &str,
+        mut cell: impl FnMut(char) -> Result<T, E>,
+    ) -> Result<Self, ParseError> {
+        let mut cells = vec![];
+        let mut width = None;
+        let mut height = 0;
+        for (i, l) in input.lines().enumerate() {
+            let line = l.trim();
+            let at = column_of(l, line);
+            let width = *width.get_or_insert_with(|| line.chars().count());
+            let mut row_width = 0;
+            for (col, c) in line.char_indices() {
+                if row_width == width {
+                    return Err(ParseError::unexpected(
+                        i + 1,
+                        at + row_width,
+                        &line[col..],
+                        "end of row",
+                    ));
+                }
+                let value = cell(c)
+                    .map_err(|e| ParseError::unexpected(i + 1, at + row_width, c, e.to_string()))?;
+                cells.push(value);
+                row_width += 1;
+            }
+            if row_width < width {
+                return Err(ParseError::missing(
+                    i + 1,
+                    at + row_width,
+                    format!("a row of {} tiles", width),
+                ));
+            }
+            height += 1;
+        }
+        Ok(Grid::new(width.unwrap_or(0), height, cells))
+    }
+}
+
+impl<T> FromStr for Grid<T>
+where
+    T: TryFrom<char>,
+    T::Error: fmt::Display,
+{
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Grid::parse_with(s, T::try_from)
+    }
+}
+
+impl<T> Index<(usize, usize)> for Grid<T> {
+    type Output = T;
+    fn index(&self, pos: (usize, usize)) -> &Self::Output {
+        match self.get(pos) {
+            Some(cell) => cell,
+            None => panic!(
+                "index out of bounds: {:?} in a {}x{} grid",
+                pos, self.width, self.height
+            ),
+        }
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Grid<T> {
+    fn index_mut(&mut self, pos: (usize, usize)) -> &mut Self::Output {
+        let (width, height) = (self.width, self.height);
+        match self.get_mut(pos) {
+            Some(cell) => cell,
+            None => panic!(
+                "index out of bounds: {:?} in a {}x{} grid",
+                pos, width, height
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Digit(u8);
+
+    impl TryFrom<char> for Digit {
+        type Error = &'static str;
+        fn try_from(c: char) -> Result<Self, Self::Error> {
+            c.to_digit(10).map(|d| Digit(d as u8)).ok_or("a digit")
+        }
+    }
+
+    fn digits(input: &str) -> Grid<u8> {
+        Grid::parse_with(input, |c| c.to_digit(10).map(|d| d as u8).ok_or("a digit")).unwrap()
+    }
+
+    #[test]
+    fn from_str() {
+        let grid: Grid<Digit> = "123\n456".parse().unwrap();
+        assert_eq!((grid.width(), grid.height()), (3, 2));
+        assert_eq!(grid[(2, 1)], Digit(6));
+        assert_eq!(
+            "12\n4x".parse::<Grid<Digit>>().unwrap_err(),
+            ParseError::unexpected(2, 2, "x", "a digit")
+        );
+        assert_eq!(
+            "12\n456".parse::<Grid<Digit>>().unwrap_err(),
+            ParseError::unexpected(2, 3, "6", "end of row")
+        );
+        assert_eq!(
+            "12\n  4".parse::<Grid<Digit>>().unwrap_err(),
+            ParseError::missing(2, 4, "a row of 2 tiles")
+        );
+        assert_eq!("".parse::<Grid<Digit>>().unwrap().height(), 0);
+    }
+
+    #[test]
+    fn edges() {
+        let grid = digits("123\n456");
+        assert_eq!(grid.get((3, 0)), None);
+        assert_eq!(grid.resolve(-1, 0), None);
+
+        let grid = grid.with_edges(Edges::WrapX);
+        assert_eq!(grid.get((3, 0)), Some(&1));
+        assert_eq!(grid.resolve(-1, 1), Some((2, 1)));
+        assert_eq!(grid.get((0, 2)), None);
+
+        let grid = grid.with_edges(Edges::WrapBoth);
+        assert_eq!(grid.get((4, 3)), Some(&5));
+        assert_eq!(grid.resolve(-1, -1), Some((2, 1)));
+
+        let grid = grid.with_edges(Edges::Clamp);
+        assert_eq!(grid.get((9, 9)), Some(&6));
+        assert_eq!(grid.resolve(-5, 1), Some((0, 1)));
+
+        assert_eq!(digits("").with_edges(Edges::WrapBoth).get((0, 0)), None);
+    }
+
+    #[test]
+    fn get_mut_and_index_mut() {
+        let mut grid = Grid::filled(2, 2, 0).with_edges(Edges::WrapBoth);
+        *grid.get_mut((3, 3)).unwrap() = 7;
+        grid[(0, 0)] += 1;
+        assert_eq!(grid.rows().collect::<Vec<_>>(), [[1, 0], [0, 7]]);
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds")]
+    fn index_out_of_bounds() {
+        let _ = digits("12")[(0, 1)];
+    }
+
+    #[test]
+    fn rows_and_columns() {
+        let grid = digits("123\n456");
+        assert_eq!(grid.row(1), Some(&[4, 5, 6][..]));
+        assert_eq!(grid.row(2), None);
+        let columns: Vec<Vec<u8>> = grid.columns().map(|c| c.copied().collect()).collect();
+        assert_eq!(columns, [[1, 4], [2, 5], [3, 6]]);
+        assert_eq!(grid.column(3).count(), 0);
+        assert_eq!(grid.iter().nth(4), Some(((1, 1), &5)));
+    }
+
+    #[test]
+    fn neighbours() {
+        let grid = digits("123\n456\n789");
+        let values = |it: &mut dyn Iterator<Item = ((usize, usize), &u8)>| -> Vec<u8> {
+            it.map(|(_, v)| *v).collect()
+        };
+        assert_eq!(values(&mut grid.neighbours4((1, 1))), [2, 4, 6, 8]);
+        assert_eq!(values(&mut grid.neighbours4((0, 0))), [2, 4]);
+        assert_eq!(values(&mut grid.neighbours8((0, 0))), [2, 4, 5]);
+        assert_eq!(values(&mut grid.neighbours8((1, 1))).len(), 8);
+
+        let grid = grid.with_edges(Edges::WrapBoth);
+        assert_eq!(values(&mut grid.neighbours4((0, 0))), [7, 3, 2, 4]);
+        let grid = grid.with_edges(Edges::Clamp);
+        assert_eq!(values(&mut grid.neighbours4((0, 0))), [2, 4]);
+    }
+}
@@ -2,6 +2,11 @@ extern crate aoc_runner;
 
 #[macro_use]
 extern crate aoc_runner_derive;
+#[macro_use]
+pub mod trace;
+
+pub mod grid;
+pub mod parse_error;
 pub mod record_parser;
 
 pub mod day1;
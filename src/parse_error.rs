@@ -0,0 +1,138 @@
+use std::error::Error;
+use std::fmt;
+
+/// Why some piece of puzzle input could not be parsed, and where.
+///
+/// Lines and columns are 1-based, columns count characters rather than bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// `text` was found where something shaped like `expected` belongs.
+    Unexpected {
+        line: usize,
+        column: usize,
+        text: String,
+        expected: String,
+    },
+    /// The line ended before something shaped like `expected` was found.
+    Missing {
+        line: usize,
+        column: usize,
+        expected: String,
+    },
+}
+
+impl ParseError {
+    pub fn unexpected(
+        line: usize,
+        column: usize,
+        text: impl Into<String>,
+        expected: impl Into<String>,
+    ) -> Self {
+        ParseError::Unexpected {
+            line,
+            column,
+            text: text.into(),
+            expected: expected.into(),
+        }
+    }
+
+    pub fn missing(line: usize, column: usize, expected: impl Into<String>) -> Self {
+        ParseError::Missing {
+            line,
+            column,
+            expected: expected.into(),
+        }
+    }
+
+    pub fn line(&self) -> usize {
+        match self {
+            ParseError::Unexpected { line, .. } | ParseError::Missing { line, .. } => *line,
+        }
+    }
+
+    pub fn column(&self) -> usize {
+        match self {
+            ParseError::Unexpected { column, .. } | ParseError::Missing { column, .. } => *column,
+        }
+    }
+
+    /// Moves an error reported relative to some snippet of input (line 1,
+    /// column 1 being the snippet's first character) to where that snippet
+    /// starts in the whole input.
+    pub fn offset(mut self, at_line: usize, at_column: usize) -> Self {
+        match &mut self {
+            ParseError::Unexpected { line, column, .. }
+            | ParseError::Missing { line, column, .. } => {
+                if *line <= 1 {
+                    *column += at_column - 1;
+                }
+                *line += at_line - 1;
+            }
+        }
+        self
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Unexpected {
+                line,
+                column,
+                text,
+                expected,
+            } => write!(
+                f,
+                "line {}, column {}: expected {}, found {:?}",
+                line, column, expected, text
+            ),
+            ParseError::Missing {
+                line,
+                column,
+                expected,
+            } => write!(
+                f,
+                "line {}, column {}: expected {}, found end of line",
+                line, column, expected
+            ),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// The 1-based column at which `part` starts, given that `part` is a slice of
+/// `line`.
+pub fn column_of(line: &str, part: &str) -> usize {
+    let offset = part.as_ptr() as usize - line.as_ptr() as usize;
+    line[..offset].chars().count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn column_of_counts_chars() {
+        let line = "ab é cd";
+        assert_eq!(column_of(line, &line[0..]), 1);
+        assert_eq!(column_of(line, &line[6..]), 6);
+    }
+
+    #[test]
+    fn offset_moves_first_line_columns_only() {
+        let e = ParseError::missing(1, 3, "a value").offset(7, 5);
+        assert_eq!((e.line(), e.column()), (7, 7));
+        let e = ParseError::missing(2, 3, "a value").offset(7, 5);
+        assert_eq!((e.line(), e.column()), (8, 3));
+    }
+
+    #[test]
+    fn display() {
+        let e = ParseError::unexpected(3, 4, "x", "`.` or `#`");
+        assert_eq!(
+            e.to_string(),
+            "line 3, column 4: expected `.` or `#`, found \"x\""
+        );
+    }
+}
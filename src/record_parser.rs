@@ -1,8 +1,53 @@
-use std::fmt::Debug;
+use crate::parse_error::{column_of, ParseError};
+use regex::Regex;
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Error as _, IntoDeserializer, MapAccess, Visitor,
+};
+use serde::forward_to_deserialize_any;
+use std::collections::HashSet;
+use std::fmt::{self, Debug};
+use std::hash::Hash;
+use std::io::{self, BufRead};
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::str::FromStr;
 
 pub trait RecordFactory<T> {
     fn new_record(&self) -> T;
     fn accept_field(&self, record: &mut T, field: &str);
+
+    /// Fallible version of `accept_field`, used by `RecordParser::try_parse`.
+    ///
+    /// Errors are positioned relative to the field itself, `try_accept_field_at`
+    /// moves them to the field's place in the input.
+    fn try_accept_field(&self, record: &mut T, field: &str) -> Result<(), ParseError> {
+        self.accept_field(record, field);
+        Ok(())
+    }
+
+    /// What the parser actually calls for each field. Override this rather
+    /// than `try_accept_field` to know where in the input the field is.
+    fn try_accept_field_at(
+        &self,
+        record: &mut T,
+        field: &str,
+        span: &Span,
+    ) -> Result<(), ParseError> {
+        self.try_accept_field(record, field)
+            .map_err(|e| e.offset(span.line, span.col))
+    }
+}
+
+/// Where a record or a field is in the parser's input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    /// Which record of the input this is, or is part of, counting from 0.
+    pub record_index: usize,
+    /// 1-based line of the first character.
+    pub line: usize,
+    /// 1-based column of the first character, counted in chars.
+    pub col: usize,
+    pub byte_range: Range<usize>,
 }
 
 pub struct DefaultRecordFactory;
@@ -17,55 +62,878 @@ impl RecordFactory<Vec<String>> for DefaultRecordFactory {
     }
 }
 
+/// How a `RecordParser` tells where one record, or one field, ends and the
+/// next begins.
+#[derive(Clone, Debug)]
+pub enum Separator {
+    /// A literal string. A `\n` in it also matches `\r\n`.
+    Literal(String),
+    /// Anything the regex matches. Empty matches are ignored.
+    ///
+    /// A match can start anywhere, so `read_records` searches a record's
+    /// whole text again after every line it reads, which is quadratic in
+    /// the length of the record. Prefer another separator for inputs with
+    /// long records.
+    Regex(Regex),
+    /// Any single char the predicate holds for, like `str::split`.
+    Predicate(fn(char) -> bool),
+    /// Never splits, the whole input is a single record.
+    WholeInput,
+    /// One or more lines that are empty or contain only whitespace.
+    BlankLine,
+    /// A line ending, `\n` or `\r\n`.
+    EachLine,
+    /// Any run of whitespace. Leading and trailing whitespace is ignored.
+    Whitespace,
+}
+
+impl From<&str> for Separator {
+    fn from(s: &str) -> Self {
+        Separator::Literal(s.into())
+    }
+}
+
+impl From<Regex> for Separator {
+    fn from(re: Regex) -> Self {
+        Separator::Regex(re)
+    }
+}
+
+/// The byte range of the first `\n` in `text`, including a `\r` before it.
+fn find_line_ending(text: &str) -> Option<(usize, usize)> {
+    let end = text.find('\n')?;
+    let start = if text[..end].ends_with('\r') {
+        end - 1
+    } else {
+        end
+    };
+    Some((start, end + 1))
+}
+
+fn find_literal(text: &str, sep: &str) -> Option<(usize, usize)> {
+    if sep.is_empty() {
+        return None;
+    }
+    'start: for (start, _) in text.char_indices() {
+        let mut end = start;
+        for c in sep.chars() {
+            let rest = &text[end..];
+            if c == '\n' && rest.starts_with("\r\n") {
+                end += 2;
+            } else if rest.starts_with(c) {
+                end += c.len_utf8();
+            } else {
+                continue 'start;
+            }
+        }
+        return Some((start, end));
+    }
+    None
+}
+
+fn find_blank_lines(text: &str) -> Option<(usize, usize)> {
+    let mut from = 0;
+    while let Some((start, line_end)) = find_line_ending(&text[from..]) {
+        let (start, mut end) = (from + start, from + line_end);
+        loop {
+            let rest = &text[end..];
+            match find_line_ending(rest) {
+                Some((blank, next)) if rest[..blank].trim().is_empty() => end += next,
+                None if !rest.is_empty() && rest.trim().is_empty() => end = text.len(),
+                _ => break,
+            }
+        }
+        if end > from + line_end {
+            return Some((start, end));
+        }
+        from = end;
+    }
+    None
+}
+
+impl Separator {
+    /// The byte range of the first separator in `text`.
+    pub fn find(&self, text: &str) -> Option<(usize, usize)> {
+        match self {
+            Separator::Literal(sep) => find_literal(text, sep),
+            Separator::Regex(re) => re
+                .find_iter(text)
+                .find(|m| !m.as_str().is_empty())
+                .map(|m| (m.start(), m.end())),
+            Separator::Predicate(pred) => text
+                .char_indices()
+                .find(|(_, c)| pred(*c))
+                .map(|(i, c)| (i, i + c.len_utf8())),
+            Separator::WholeInput => None,
+            Separator::BlankLine => find_blank_lines(text),
+            Separator::EachLine => find_line_ending(text),
+            Separator::Whitespace => {
+                let start = text.find(char::is_whitespace)?;
+                let end = text[start..]
+                    .find(|c: char| !c.is_whitespace())
+                    .map_or(text.len(), |i| start + i);
+                Some((start, end))
+            }
+        }
+    }
+
+    /// Where to search `text` again for the first separator once more text
+    /// has been appended to it, given that the first `searched` bytes were
+    /// already searched and `found` is where a match started, if one did.
+    /// Matches starting before the returned offset have already been seen.
+    fn resume_at(&self, text: &str, searched: usize, found: Option<usize>) -> usize {
+        let back_to_char = |mut i: usize| {
+            while !text.is_char_boundary(i) {
+                i -= 1;
+            }
+            i
+        };
+        match (self, found) {
+            // Even a match found so far may turn out to start earlier once
+            // the text is longer, e.g. `x\n\n|\n`, so start over.
+            (Separator::Regex(_), _) => 0,
+            (_, Some(start)) => start,
+            // Never matches, like `WholeInput`.
+            (Separator::Literal(sep), None) if sep.is_empty() => searched,
+            (Separator::Literal(sep), None) => {
+                let longest = sep.len() + sep.matches('\n').count();
+                back_to_char(searched.saturating_sub(longest - 1))
+            }
+            (Separator::EachLine, None) => back_to_char(searched.saturating_sub(1)),
+            (Separator::BlankLine, None) => match text[..searched].rfind('\n') {
+                Some(end) if text[..end].ends_with('\r') => end - 1,
+                Some(end) => end,
+                None => searched,
+            },
+            (Separator::Predicate(_), None)
+            | (Separator::Whitespace, None)
+            | (Separator::WholeInput, None) => searched,
+        }
+    }
+
+    /// Splits `text` on this separator without copying it.
+    pub fn split<'a>(&'a self, text: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        let text = match self {
+            Separator::Whitespace => text.trim(),
+            _ => text,
+        };
+        let mut rest = Some(text);
+        std::iter::from_fn(move || {
+            let text = rest?;
+            match self.find(text) {
+                Some((start, end)) => {
+                    rest = Some(&text[end..]);
+                    Some(&text[..start])
+                }
+                None => rest.take(),
+            }
+        })
+        .filter(move |piece| !piece.is_empty() || !matches!(self, Separator::Whitespace))
+    }
+}
+
+/// What a `RecordParser` does with records that are empty or only contain
+/// whitespace. Text after a trailing record separator is never a record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmptyRecordPolicy {
+    KeepEmpty,
+    SkipEmpty,
+    ErrorOnEmpty,
+}
+
 pub struct RecordParser {
-    pub rec_sep: String,
-    pub field_sep: String,
+    pub rec_sep: Separator,
+    pub field_sep: Separator,
+    pub empty_records: EmptyRecordPolicy,
 }
 
 impl Default for RecordParser {
     fn default() -> Self {
         RecordParser {
-            rec_sep: "\n\n".into(),
-            field_sep: " ".into(),
+            rec_sep: Separator::BlankLine,
+            field_sep: Separator::Whitespace,
+            empty_records: EmptyRecordPolicy::SkipEmpty,
+        }
+    }
+}
+
+/// Strips one trailing line ending, if any.
+fn trim_line_ending(text: &str) -> &str {
+    let text = text.strip_suffix('\n').unwrap_or(text);
+    text.strip_suffix('\r').unwrap_or(text)
+}
+
+fn count_lines(text: &str) -> usize {
+    text.bytes().filter(|b| *b == b'\n').count()
+}
+
+/// The line and column just past `text`, given that it starts at `line`,
+/// `col`.
+fn advance(line: usize, col: usize, text: &str) -> (usize, usize) {
+    match text.rfind('\n') {
+        Some(i) => (line + count_lines(text), text[i + 1..].chars().count() + 1),
+        None => (line, col + text.chars().count()),
+    }
+}
+
+/// Position of the next record while splitting an input into records.
+struct Cursor {
+    byte: usize,
+    line: usize,
+    col: usize,
+}
+
+impl Cursor {
+    fn new() -> Self {
+        Cursor {
+            byte: 0,
+            line: 1,
+            col: 1,
+        }
+    }
+
+    /// Span of a record of `len` bytes at the cursor, after which the cursor
+    /// skips over `consumed`, the record and its separator. Records are
+    /// numbered once empty ones have been skipped.
+    fn take(&mut self, len: usize, consumed: &str) -> Span {
+        let span = Span {
+            record_index: 0,
+            line: self.line,
+            col: self.col,
+            byte_range: self.byte..self.byte + len,
+        };
+        self.byte += consumed.len();
+        let (line, col) = advance(self.line, self.col, consumed);
+        self.line = line;
+        self.col = col;
+        span
+    }
+}
+
+/// Borrowed records of an input, see `RecordParser::records`.
+struct Records<'a> {
+    input: &'a str,
+    rec_sep: &'a Separator,
+    cursor: Cursor,
+    done: bool,
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = (Span, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let rest = &self.input[self.cursor.byte..];
+        let record = match self.rec_sep.find(rest) {
+            Some((start, end)) => {
+                let record = &rest[..start];
+                Some((self.cursor.take(record.len(), &rest[..end]), record))
+            }
+            None => {
+                self.done = true;
+                let record = trim_line_ending(rest);
+                if record.is_empty() {
+                    return None;
+                }
+                Some((self.cursor.take(record.len(), rest), record))
+            }
+        };
+        record
+    }
+}
+
+/// Owned records read from a `BufRead`, see `RecordParser::read_records`.
+///
+/// A separator is only trusted once some text follows it, or the input has
+/// ended, so that greedy separators such as `BlankLine` see all of their
+/// match before the record is split off.
+struct ReadRecords<'a, R> {
+    reader: R,
+    rec_sep: &'a Separator,
+    buf: String,
+    /// Where in `buf` a separator could still start.
+    search_from: usize,
+    cursor: Cursor,
+    eof: bool,
+    done: bool,
+}
+
+impl<'a, R: BufRead> Iterator for ReadRecords<'a, R> {
+    type Item = io::Result<(Span, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        loop {
+            let from = self.search_from;
+            let found = self.rec_sep.find(&self.buf[from..]);
+            match found.map(|(start, end)| (from + start, from + end)) {
+                Some((start, end)) if self.eof || end < self.buf.len() => {
+                    let record = self.buf[..start].to_string();
+                    let span = self.cursor.take(start, &self.buf[..end]);
+                    self.buf.drain(..end);
+                    self.search_from = 0;
+                    return Some(Ok((span, record)));
+                }
+                _ if self.eof => {
+                    self.done = true;
+                    let record = trim_line_ending(&self.buf);
+                    if record.is_empty() {
+                        return None;
+                    }
+                    let span = self.cursor.take(record.len(), &self.buf);
+                    return Some(Ok((span, record.into())));
+                }
+                found => {
+                    self.search_from = self.rec_sep.resume_at(
+                        &self.buf,
+                        self.buf.len(),
+                        found.map(|(start, _)| start),
+                    )
+                }
+            }
+            match self.reader.read_line(&mut self.buf) {
+                Ok(0) => self.eof = true,
+                Ok(_) => (),
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
         }
     }
 }
 
 impl RecordParser {
-    fn parse_record_string<T>(&mut self, factory: &impl RecordFactory<T>, record_string: &str) -> T
-    where
-        T: Debug,
-    {
-        let field_sep = self.field_sep.clone();
+    fn parse_record_string<T>(
+        &self,
+        factory: &impl RecordFactory<T>,
+        record_string: &str,
+        span: &Span,
+    ) -> Result<T, ParseError> {
+        if self.empty_records == EmptyRecordPolicy::ErrorOnEmpty && record_string.trim().is_empty()
+        {
+            return Err(ParseError::unexpected(
+                span.line,
+                span.col,
+                record_string,
+                "a non-empty record",
+            ));
+        }
         let mut record = factory.new_record();
-        for rline in record_string.lines() {
+        for (i, rline) in record_string.lines().enumerate() {
             if rline.is_empty() {
                 continue;
             }
-            for field_str in rline.split(&field_sep) {
-                factory.accept_field(&mut record, field_str);
+            for field_str in self.field_sep.split(rline) {
+                let offset = field_str.as_ptr() as usize - record_string.as_ptr() as usize;
+                let start = span.byte_range.start + offset;
+                let col = column_of(rline, field_str);
+                let field_span = Span {
+                    record_index: span.record_index,
+                    line: span.line + i,
+                    col: if i == 0 { span.col + col - 1 } else { col },
+                    byte_range: start..start + field_str.len(),
+                };
+                factory.try_accept_field_at(&mut record, field_str, &field_span)?;
             }
         }
-        record
+        Ok(record)
+    }
+
+    /// Splits `input` into records without copying it. The record separator
+    /// itself is not part of the returned records.
+    pub fn records<'a>(&'a self, input: &'a str) -> impl Iterator<Item = &'a str> {
+        self.spanned_records(input).map(|(_, record)| record)
+    }
+
+    /// Like `records`, along with where each record is in `input`.
+    pub fn spanned_records<'a>(
+        &'a self,
+        input: &'a str,
+    ) -> impl Iterator<Item = (Span, &'a str)> + 'a {
+        let records = Records {
+            input,
+            rec_sep: &self.rec_sep,
+            cursor: Cursor::new(),
+            done: false,
+        };
+        records
+            .filter(move |(_, record)| !self.is_skipped(record))
+            .enumerate()
+            .map(|(i, (mut span, record))| {
+                span.record_index = i;
+                (span, record)
+            })
+    }
+
+    fn is_skipped(&self, record: &str) -> bool {
+        self.empty_records == EmptyRecordPolicy::SkipEmpty && record.trim().is_empty()
     }
 
-    pub fn parse<T>(&mut self, factory: impl RecordFactory<T>, input: &str) -> Vec<T>
+    /// Like `records` but reads `reader` one line at a time, so only the
+    /// record currently being read is held in memory. Each line only costs
+    /// a search of little more than its own text, except with `Separator::Regex`, where it
+    /// costs a search of the whole record so far.
+    pub fn read_records<'a, R: BufRead + 'a>(
+        &'a self,
+        reader: R,
+    ) -> impl Iterator<Item = io::Result<String>> + 'a {
+        self.spanned_read_records(reader)
+            .map(|r| r.map(|(_, record)| record))
+    }
+
+    /// Like `read_records`, along with where each record is in the input.
+    pub fn spanned_read_records<'a, R: BufRead + 'a>(
+        &'a self,
+        reader: R,
+    ) -> impl Iterator<Item = io::Result<(Span, String)>> + 'a {
+        let records = ReadRecords {
+            reader,
+            rec_sep: &self.rec_sep,
+            buf: String::new(),
+            search_from: 0,
+            cursor: Cursor::new(),
+            eof: false,
+            done: false,
+        };
+        let mut index = 0;
+        records
+            .filter(move |r| !matches!(r, Ok((_, record)) if self.is_skipped(record)))
+            .map(move |r| {
+                r.map(|(mut span, record)| {
+                    span.record_index = index;
+                    index += 1;
+                    (span, record)
+                })
+            })
+    }
+
+    pub fn parse<T>(&self, factory: impl RecordFactory<T>, input: &str) -> Vec<T>
     where
         T: Debug,
     {
-        let mut records = vec![];
-        let record_sep = self.rec_sep.clone();
-        let mut record_str = "".to_string();
-        for line in input.lines() {
-            record_str.push_str(line);
-            record_str.push('\n');
-            if record_str.ends_with(&record_sep) {
-                records.push(self.parse_record_string(&factory, &record_str));
-                record_str = "".to_string();
-            }
-        }
-        records.push(self.parse_record_string(&factory, &record_str));
-        records
+        self.parse_iter(factory, input).collect()
+    }
+
+    pub fn try_parse<T>(
+        &self,
+        factory: impl RecordFactory<T>,
+        input: &str,
+    ) -> Result<Vec<T>, ParseError>
+    where
+        T: Debug,
+    {
+        self.try_parse_iter(factory, input).collect()
+    }
+
+    /// Lazy version of `parse`, the factory is only called for a record once
+    /// that record is consumed.
+    pub fn parse_iter<'a, T, F>(
+        &'a self,
+        factory: F,
+        input: &'a str,
+    ) -> impl Iterator<Item = T> + 'a
+    where
+        F: RecordFactory<T> + 'a,
+    {
+        self.try_parse_iter(factory, input)
+            .map(|r| r.unwrap_or_else(|e| panic!("{}", e)))
+    }
+
+    /// Lazy version of `try_parse`.
+    pub fn try_parse_iter<'a, T, F>(
+        &'a self,
+        factory: F,
+        input: &'a str,
+    ) -> impl Iterator<Item = Result<T, ParseError>> + 'a
+    where
+        F: RecordFactory<T> + 'a,
+    {
+        self.spanned_records(input)
+            .map(move |(span, record)| self.parse_record_string(&factory, record, &span))
+    }
+
+    /// Parses records read one at a time from `reader`. Parse errors are
+    /// reported as `io::ErrorKind::InvalidData` wrapping a `ParseError`.
+    pub fn parse_reader<'a, T, F, R>(
+        &'a self,
+        factory: F,
+        reader: R,
+    ) -> impl Iterator<Item = io::Result<T>> + 'a
+    where
+        F: RecordFactory<T> + 'a,
+        R: BufRead + 'a,
+    {
+        self.spanned_read_records(reader).map(move |r| {
+            let (span, record) = r?;
+            self.parse_record_string(&factory, &record, &span)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        })
+    }
+}
+
+/// Reads each field as the set of its chars, so that a record becomes a
+/// group of sets.
+pub struct SetGroupFactory<T>(PhantomData<fn() -> T>);
+
+impl<T> Default for SetGroupFactory<T> {
+    fn default() -> Self {
+        SetGroupFactory(PhantomData)
+    }
+}
+
+impl<T> SetGroupFactory<T> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl<T> RecordFactory<Vec<HashSet<T>>> for SetGroupFactory<T>
+where
+    T: From<char> + Eq + Hash,
+{
+    fn new_record(&self) -> Vec<HashSet<T>> {
+        vec![]
+    }
+
+    fn accept_field(&self, record: &mut Vec<HashSet<T>>, field: &str) {
+        record.push(field.chars().map(T::from).collect());
+    }
+}
+
+/// Collects `key:value` fields and deserializes them into any `T`.
+///
+/// Values are handed to `T` as strings but are parsed on demand when `T`
+/// asks for a number, bool or char. Keys that `T` has no field for are an
+/// error unless `allow_unknown_fields` is set, in which case they are dropped.
+pub struct SerdeRecordFactory<T> {
+    allow_unknown: bool,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for SerdeRecordFactory<T> {
+    fn default() -> Self {
+        SerdeRecordFactory {
+            allow_unknown: false,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: DeserializeOwned> SerdeRecordFactory<T> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn allow_unknown_fields(mut self, allow: bool) -> Self {
+        self.allow_unknown = allow;
+        self
+    }
+
+    /// Deserializes a record collected by this factory. Errors about a
+    /// single field point at its value, other errors at the record.
+    pub fn deserialize(
+        &self,
+        record: &Span,
+        fields: &[(String, String, Span)],
+    ) -> Result<T, ParseError> {
+        T::deserialize(FieldsDeserializer {
+            fields,
+            allow_unknown: self.allow_unknown,
+        })
+        .map_err(|e| match e.field {
+            Some(i) => {
+                let (key, value, span) = &fields[i];
+                if e.in_key {
+                    return ParseError::unexpected(span.line, span.col, key, e.expected);
+                }
+                let col = span.col + key.chars().count() + 1;
+                ParseError::unexpected(span.line, col, value, e.expected)
+            }
+            None => ParseError::missing(record.line, record.col, e.expected),
+        })
+    }
+
+    pub fn parse(&self, parser: &RecordParser, input: &str) -> Result<Vec<T>, ParseError> {
+        self.parse_iter(parser, input).collect()
+    }
+
+    /// Lazily parses and deserializes each record of `input`.
+    pub fn parse_iter<'a>(
+        &'a self,
+        parser: &'a RecordParser,
+        input: &'a str,
+    ) -> impl Iterator<Item = Result<T, ParseError>> + 'a {
+        parser.spanned_records(input).map(move |(span, record)| {
+            let fields = parser.parse_record_string(self, record, &span)?;
+            self.deserialize(&span, &fields)
+        })
+    }
+}
+
+impl<T> RecordFactory<Vec<(String, String, Span)>> for SerdeRecordFactory<T> {
+    fn new_record(&self) -> Vec<(String, String, Span)> {
+        vec![]
+    }
+
+    fn accept_field(&self, record: &mut Vec<(String, String, Span)>, field: &str) {
+        self.try_accept_field(record, field)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_accept_field(
+        &self,
+        record: &mut Vec<(String, String, Span)>,
+        field: &str,
+    ) -> Result<(), ParseError> {
+        let span = Span {
+            record_index: 0,
+            line: 1,
+            col: 1,
+            byte_range: 0..field.len(),
+        };
+        self.try_accept_field_at(record, field, &span)
+    }
+
+    fn try_accept_field_at(
+        &self,
+        record: &mut Vec<(String, String, Span)>,
+        field: &str,
+        span: &Span,
+    ) -> Result<(), ParseError> {
+        let (key, value) = field.split_once(':').ok_or_else(|| {
+            ParseError::missing(
+                span.line,
+                span.col + field.chars().count(),
+                "`:` between key and value",
+            )
+        })?;
+        record.push((key.into(), value.into(), span.clone()));
+        Ok(())
+    }
+}
+
+/// Deserialization failure of one record, `field` indexes the offending
+/// field when there is one and `in_key` tells whether its key or its value
+/// is to blame.
+#[derive(Debug)]
+struct FieldError {
+    field: Option<usize>,
+    in_key: bool,
+    expected: String,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected {}", self.expected)
+    }
+}
+
+impl std::error::Error for FieldError {}
+
+impl de::Error for FieldError {
+    fn custom<M: fmt::Display>(msg: M) -> Self {
+        FieldError {
+            field: None,
+            in_key: false,
+            expected: msg.to_string(),
+        }
+    }
+
+    fn invalid_type(_: de::Unexpected, exp: &dyn de::Expected) -> Self {
+        Self::custom(exp)
+    }
+
+    fn invalid_value(_: de::Unexpected, exp: &dyn de::Expected) -> Self {
+        Self::custom(exp)
+    }
+
+    fn unknown_field(_: &str, expected: &'static [&'static str]) -> Self {
+        Self::custom(format!("one of {}", expected.join(", ")))
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        Self::custom(format!("a `{}` field", field))
+    }
+
+    fn duplicate_field(field: &'static str) -> Self {
+        Self::custom(format!("a single `{}` field", field))
+    }
+}
+
+struct FieldsDeserializer<'a> {
+    fields: &'a [(String, String, Span)],
+    allow_unknown: bool,
+}
+
+impl<'de, 'a> de::Deserializer<'de> for FieldsDeserializer<'a> {
+    type Error = FieldError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(FieldsAccess {
+            fields: self.fields,
+            known: None,
+            next: 0,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        known: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if !self.allow_unknown {
+            if let Some(i) = self
+                .fields
+                .iter()
+                .position(|(k, _, _)| !known.contains(&k.as_str()))
+            {
+                let mut e = FieldError::unknown_field(&self.fields[i].0, known);
+                e.field = Some(i);
+                e.in_key = true;
+                return Err(e);
+            }
+        }
+        visitor.visit_map(FieldsAccess {
+            fields: self.fields,
+            known: Some(known),
+            next: 0,
+        })
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+struct FieldsAccess<'a> {
+    fields: &'a [(String, String, Span)],
+    known: Option<&'static [&'static str]>,
+    next: usize,
+}
+
+impl<'de, 'a> MapAccess<'de> for FieldsAccess<'a> {
+    type Error = FieldError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        while let Some((key, _, _)) = self.fields.get(self.next) {
+            let wanted = match self.known {
+                Some(known) => known.contains(&key.as_str()),
+                None => true,
+            };
+            if wanted {
+                return seed
+                    .deserialize(FieldValue(key))
+                    .map(Some)
+                    .map_err(|e| self.at_field(e));
+            }
+            self.next += 1;
+        }
+        Ok(None)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let (_, value, _) = &self.fields[self.next];
+        let value = seed
+            .deserialize(FieldValue(value))
+            .map_err(|e| self.at_field(e));
+        self.next += 1;
+        value
+    }
+}
+
+impl<'a> FieldsAccess<'a> {
+    fn at_field(&self, mut e: FieldError) -> FieldError {
+        e.field.get_or_insert(self.next);
+        e
+    }
+}
+
+/// A single field value, parsed into whatever type is asked of it.
+struct FieldValue<'a>(&'a str);
+
+impl<'a> FieldValue<'a> {
+    fn parse<'de, V, N>(self, visitor: &V) -> Result<N, FieldError>
+    where
+        V: Visitor<'de>,
+        N: FromStr,
+    {
+        self.0
+            .parse()
+            .map_err(|_| FieldError::invalid_value(de::Unexpected::Str(self.0), visitor))
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($deserialize:ident => $visit:ident,)*) => {
+        $(
+            fn $deserialize<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                let value = self.parse(&visitor)?;
+                visitor.$visit(value)
+            }
+        )*
+    };
+}
+
+impl<'de, 'a> de::Deserializer<'de> for FieldValue<'a> {
+    type Error = FieldError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_str(self.0)
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_enum(self.0.into_deserializer())
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
     }
 }
 
@@ -73,7 +941,7 @@ impl RecordParser {
 mod tests {
     use super::*;
     use serde::{Deserialize, Serialize};
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
     pub struct Party {
         hey: u32,
         we: u32,
@@ -94,10 +962,424 @@ mod tests {
             doo:88
         "};
 
-        let mut parser: RecordParser = Default::default();
+        let parser: RecordParser = Default::default();
         let out: Vec<Vec<String>> = parser.parse(DefaultRecordFactory, input);
-        for party in out {
-            println!("{:?}", party);
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[1], ["hey:99", "we:182", "doo:88"]);
+    }
+
+    struct StrictFactory;
+
+    impl RecordFactory<Vec<String>> for StrictFactory {
+        fn new_record(&self) -> Vec<String> {
+            vec![]
+        }
+
+        fn accept_field(&self, record: &mut Vec<String>, field: &str) {
+            self.try_accept_field(record, field)
+                .unwrap_or_else(|e| panic!("{}", e))
+        }
+
+        fn try_accept_field(
+            &self,
+            record: &mut Vec<String>,
+            field: &str,
+        ) -> Result<(), ParseError> {
+            match field.find(':') {
+                Some(_) => {
+                    record.push(field.into());
+                    Ok(())
+                }
+                None => Err(ParseError::missing(1, field.chars().count() + 1, "`:`")),
+            }
+        }
+    }
+
+    #[test]
+    fn try_parse_reports_field_position() {
+        let input = "hey:22 we:21\n\nhey:99\nwe:182 doo\n";
+        let parser: RecordParser = Default::default();
+        let err = parser.try_parse(StrictFactory, input).unwrap_err();
+        assert_eq!(err, ParseError::missing(4, 11, "`:`"));
+    }
+
+    #[test]
+    fn records_borrow_input() {
+        let input = "a:1 b:2\nc:3\n\nd:4\n\ne:5\n";
+        let parser: RecordParser = Default::default();
+        let records: Vec<&str> = parser.records(input).collect();
+        assert_eq!(records, vec!["a:1 b:2\nc:3", "d:4", "e:5"]);
+        let range = input.as_bytes().as_ptr_range();
+        assert!(records
+            .iter()
+            .all(|r| range.contains(&r.as_ptr()) || r.is_empty()));
+    }
+
+    #[test]
+    fn records_crlf() {
+        let input = "a:1 b:2\r\nc:3\r\n\r\nd:4\r\n";
+        let parser: RecordParser = Default::default();
+        let records: Vec<&str> = parser.records(input).collect();
+        assert_eq!(records, vec!["a:1 b:2\r\nc:3", "d:4"]);
+        let out = parser.parse(DefaultRecordFactory, input);
+        assert_eq!(out, vec![vec!["a:1", "b:2", "c:3"], vec!["d:4"]]);
+    }
+
+    struct CountingFactory<'a>(&'a std::cell::Cell<usize>);
+
+    impl RecordFactory<Vec<String>> for CountingFactory<'_> {
+        fn new_record(&self) -> Vec<String> {
+            self.0.set(self.0.get() + 1);
+            vec![]
+        }
+
+        fn accept_field(&self, record: &mut Vec<String>, field: &str) {
+            record.push(field.into());
+        }
+    }
+
+    #[test]
+    fn parse_iter_is_lazy() {
+        let input = "a\n\nb\n\nc";
+        let parser: RecordParser = Default::default();
+        let made = std::cell::Cell::new(0);
+        let mut records = parser.parse_iter(CountingFactory(&made), input);
+        assert_eq!(made.get(), 0);
+        assert_eq!(records.next(), Some(vec!["a".to_string()]));
+        assert_eq!(made.get(), 1);
+    }
+
+    #[test]
+    fn parse_reader_matches_parse() {
+        let input = "hey:22 we:21\nlike:12\n\nhey:99\nwe:182\n";
+        let parser: RecordParser = Default::default();
+        let read: Vec<Vec<String>> = parser
+            .parse_reader(DefaultRecordFactory, io::Cursor::new(input))
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(read, parser.parse(DefaultRecordFactory, input));
+
+        let err = parser
+            .parse_reader(StrictFactory, io::Cursor::new("a:1\n\nb:2 c\n"))
+            .find_map(Result::err)
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "line 3, column 6: expected `:`, found end of line"
+        );
+    }
+
+    #[test]
+    fn serde_record_factory() {
+        use indoc::indoc;
+        let input = indoc! {"
+            hey:22 we:21
+            like:12 to:29 party:99
+
+            hey:99
+            we:182
+            doo:88
+        "};
+
+        let parser: RecordParser = Default::default();
+        let out = SerdeRecordFactory::<Party>::new().parse(&parser, input);
+        assert_eq!(
+            out,
+            Ok(vec![
+                Party {
+                    hey: 22,
+                    we: 21,
+                    doo: None,
+                    like: Some(12),
+                    to: Some(29),
+                    party: Some(99),
+                },
+                Party {
+                    hey: 99,
+                    we: 182,
+                    doo: Some(88),
+                    like: None,
+                    to: None,
+                    party: None,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn serde_record_factory_errors() {
+        let parser: RecordParser = Default::default();
+        let factory = SerdeRecordFactory::<Party>::new();
+        assert_eq!(
+            factory.parse(&parser, "hey:1 we:2\n\nhey:1\nwe:x"),
+            Err(ParseError::unexpected(4, 4, "x", "u32"))
+        );
+        assert_eq!(
+            factory.parse(&parser, "hey:1"),
+            Err(ParseError::missing(1, 1, "a `we` field"))
+        );
+        assert_eq!(
+            factory.parse(&parser, "hey:1 we:2 you:3"),
+            Err(ParseError::unexpected(
+                1,
+                12,
+                "you",
+                "one of hey, we, doo, like, to, party"
+            ))
+        );
+        assert_eq!(
+            factory.parse(&parser, "hey:1 we 2"),
+            Err(ParseError::missing(1, 9, "`:` between key and value"))
+        );
+
+        let factory = factory.allow_unknown_fields(true);
+        let out = factory.parse(&parser, "hey:1 we:2 you:3").unwrap();
+        assert_eq!((out[0].hey, out[0].we), (1, 2));
+    }
+
+    #[test]
+    fn separator_modes() {
+        let input = "hey:22   we:21\r\n\tlike:12\r\n \r\n\r\nhey:99\r\n";
+        let parser: RecordParser = Default::default();
+        let out = parser.parse(DefaultRecordFactory, input);
+        assert_eq!(
+            out,
+            vec![vec!["hey:22", "we:21", "like:12"], vec!["hey:99"]]
+        );
+
+        let parser = RecordParser {
+            rec_sep: Separator::WholeInput,
+            field_sep: Separator::EachLine,
+            ..Default::default()
+        };
+        let out = parser.parse(DefaultRecordFactory, "a b\r\nc d\n");
+        assert_eq!(out, vec![vec!["a b", "c d"]]);
+
+        let parser = RecordParser {
+            rec_sep: Separator::EachLine,
+            field_sep: Separator::Predicate(|c| c == ',' || c == ';'),
+            ..Default::default()
+        };
+        let out = parser.parse(DefaultRecordFactory, "a,b;c\r\nd");
+        assert_eq!(out, vec![vec!["a", "b", "c"], vec!["d"]]);
+
+        let parser = RecordParser {
+            rec_sep: Regex::new(r"\s*---\s*").unwrap().into(),
+            field_sep: ", ".into(),
+            ..Default::default()
+        };
+        let records: Vec<&str> = parser.records("a, b\n---\nc --- d").collect();
+        assert_eq!(records, vec!["a, b", "c", "d"]);
+    }
+
+    #[test]
+    fn read_records_waits_for_whole_separator() {
+        let lf = "a\n\n \n\nb c\nd\n\n\n\ne\nf é--\ng\n--\n\nh";
+        let crlf = lf.replace('\n', "\r\n");
+        let blank_lines = Regex::new(r"\n\n+").unwrap();
+        for input in [lf, crlf.as_str()] {
+            for rec_sep in [
+                Separator::BlankLine,
+                Separator::EachLine,
+                Separator::Whitespace,
+                Separator::Predicate(|c| c == 'é'),
+                Separator::Regex(blank_lines.clone()),
+                "\n\n".into(),
+                "--\n".into(),
+                "".into(),
+            ] {
+                let parser = RecordParser {
+                    rec_sep,
+                    field_sep: Separator::Whitespace,
+                    ..Default::default()
+                };
+                let read: Vec<String> = parser
+                    .read_records(io::Cursor::new(input))
+                    .collect::<io::Result<_>>()
+                    .unwrap();
+                let records: Vec<&str> = parser.records(input).collect();
+                assert_eq!(read, records, "{:?} in {:?}", parser.rec_sep, input);
+            }
+        }
+    }
+
+    struct SpanFactory;
+
+    impl RecordFactory<Vec<Span>> for SpanFactory {
+        fn new_record(&self) -> Vec<Span> {
+            vec![]
+        }
+
+        fn accept_field(&self, _: &mut Vec<Span>, _: &str) {}
+
+        fn try_accept_field_at(
+            &self,
+            record: &mut Vec<Span>,
+            _: &str,
+            span: &Span,
+        ) -> Result<(), ParseError> {
+            record.push(span.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn field_spans() {
+        let input = "é:1 b:2\r\n\r\nc:3\r\n  d:4 --- e:5";
+        let parser = RecordParser {
+            rec_sep: Regex::new(r"\r\n\r\n| --- ").unwrap().into(),
+            field_sep: Separator::Whitespace,
+            ..Default::default()
+        };
+        let expected = vec![
+            vec![(0, 1, 1, "é:1"), (0, 1, 5, "b:2")],
+            vec![(1, 3, 1, "c:3"), (1, 4, 3, "d:4")],
+            vec![(2, 4, 11, "e:5")],
+        ];
+        let check = |records: Vec<Vec<Span>>| {
+            let found: Vec<Vec<_>> = records
+                .into_iter()
+                .map(|spans| {
+                    spans
+                        .into_iter()
+                        .map(|s| (s.record_index, s.line, s.col, &input[s.byte_range]))
+                        .collect()
+                })
+                .collect();
+            assert_eq!(found, expected);
+        };
+        check(parser.parse(SpanFactory, input));
+        check(
+            parser
+                .parse_reader(SpanFactory, io::Cursor::new(input))
+                .collect::<io::Result<_>>()
+                .unwrap(),
+        );
+    }
+
+    #[test]
+    fn empty_record_policy() {
+        let input = "a\n\nb\n  \nc\n";
+        let mut parser = RecordParser {
+            rec_sep: Separator::EachLine,
+            ..Default::default()
+        };
+        let records: Vec<&str> = parser.records(input).collect();
+        assert_eq!(records, vec!["a", "b", "c"]);
+
+        parser.empty_records = EmptyRecordPolicy::KeepEmpty;
+        let records: Vec<&str> = parser.records(input).collect();
+        assert_eq!(records, vec!["a", "", "b", "  ", "c"]);
+
+        parser.empty_records = EmptyRecordPolicy::ErrorOnEmpty;
+        assert_eq!(
+            parser.try_parse(DefaultRecordFactory, input),
+            Err(ParseError::unexpected(2, 1, "", "a non-empty record"))
+        );
+        assert_eq!(
+            parser
+                .try_parse(DefaultRecordFactory, "a\nb\n")
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    /// Parses `input` with every kind of line ending and trailing blank
+    /// lines, checks that each gives `count` identical records, and that
+    /// writing those records back out with `rec_sep` and `field_sep` and
+    /// parsing them again gives the same records.
+    fn check_layout(
+        parser: &RecordParser,
+        input: &str,
+        count: usize,
+        rec_sep: &str,
+        field_sep: &str,
+    ) {
+        let expected = parser.parse(DefaultRecordFactory, input);
+        assert_eq!(expected.len(), count);
+        for ending in &["", "\n", "\n\n", "\n\n\n"] {
+            let input = format!("{}{}", input.trim_end(), ending);
+            for input in [input.clone(), input.replace('\n', "\r\n")] {
+                assert_eq!(
+                    parser.parse(DefaultRecordFactory, &input),
+                    expected,
+                    "{:?}",
+                    input
+                );
+                let read: Vec<Vec<String>> = parser
+                    .parse_reader(DefaultRecordFactory, io::Cursor::new(&input))
+                    .collect::<io::Result<_>>()
+                    .unwrap();
+                assert_eq!(read, expected, "{:?}", input);
+            }
         }
+        let written = expected
+            .iter()
+            .map(|record| record.join(field_sep))
+            .collect::<Vec<_>>()
+            .join(rec_sep);
+        assert_eq!(parser.parse(DefaultRecordFactory, &written), expected);
+    }
+
+    #[test]
+    fn day4_layout() {
+        let input = indoc::indoc! {"
+            ecl:gry pid:860033327 eyr:2020 hcl:#fffffd
+            byr:1937 iyr:2017 cid:147 hgt:183cm
+
+            iyr:2013 ecl:amb cid:350 eyr:2023 pid:028048884
+            hcl:#cfa07d byr:1929
+
+            hcl:#ae17e1 iyr:2013
+            eyr:2024
+            ecl:brn pid:760753108 byr:1931
+            hgt:179cm
+        "};
+        let parser: RecordParser = Default::default();
+        check_layout(&parser, input, 3, "\n\n", " ");
+    }
+
+    #[test]
+    fn day6_layout() {
+        let input = "abc\n\na\nb\nc\n\nab\nac\n\na\na\na\na\n\nb\n";
+        let parser: RecordParser = Default::default();
+        check_layout(&parser, input, 5, "\n\n", "\n");
+    }
+
+    #[test]
+    fn day7_layout() {
+        let input = indoc::indoc! {"
+            light red bags contain 1 bright white bag, 2 muted yellow bags.
+            bright white bags contain 1 shiny gold bag.
+            faded blue bags contain no other bags.
+        "};
+        let parser = RecordParser {
+            rec_sep: Separator::WholeInput,
+            field_sep: Separator::EachLine,
+            ..Default::default()
+        };
+        check_layout(&parser, input, 1, "", "\n");
+        assert_eq!(parser.parse(DefaultRecordFactory, input)[0].len(), 3);
+    }
+
+    #[test]
+    fn day8_layout() {
+        let input = "nop +0\nacc +1\njmp +4\nacc +3\njmp -3\n";
+        let parser = RecordParser {
+            rec_sep: Separator::EachLine,
+            field_sep: Separator::EachLine,
+            ..Default::default()
+        };
+        check_layout(&parser, input, 5, "\n", "");
+    }
+
+    #[test]
+    fn set_group_factory() {
+        let set = |s: &str| s.chars().collect::<HashSet<char>>();
+        let parser: RecordParser = Default::default();
+        let groups: Vec<Vec<HashSet<char>>> = parser.parse(SetGroupFactory::new(), "ab\nbc\n\nz\n");
+        assert_eq!(groups, vec![vec![set("ab"), set("bc")], vec![set("z")]]);
     }
 }
@@ -0,0 +1,210 @@
+//! Diagnostics for solvers, quiet unless asked for.
+//!
+//! The runner binary is generated by `cargo aoc`, so there is no `main` of
+//! ours to install a logger from. Instead the filter is read from `AOC_LOG`
+//! the first time anything asks whether it is enabled:
+//!
+//! ```text
+//! AOC_LOG=debug              # everything at debug and above
+//! AOC_LOG=day3=trace         # only day3, including the map render
+//! AOC_LOG=warn,day2=debug    # warnings everywhere, day2's invalid passwords
+//! ```
+//!
+//! Targets are module paths without the crate name, so each day is its own
+//! target and `day4` also covers anything under `day4::`. Output goes to
+//! stderr so it never mixes with answers.
+
+use std::env;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// The variable `filter` is read from.
+pub const ENV_VAR: &str = "AOC_LOG";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn parse(name: &str) -> Option<Option<Level>> {
+        let level = match name.to_ascii_lowercase().as_str() {
+            "off" => None,
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => return None,
+        };
+        Some(level)
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The most verbose level to emit, overall and per target. `None` is off.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    default: Option<Level>,
+    targets: Vec<(String, Option<Level>)>,
+}
+
+impl Filter {
+    /// Parses comma separated `level` and `target=level` directives.
+    /// Directives that don't parse are ignored rather than failing the run.
+    pub fn parse(spec: &str) -> Self {
+        let mut filter = Filter::default();
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    if let Some(level) = Level::parse(level.trim()) {
+                        filter.targets.push((target.trim().into(), level));
+                    }
+                }
+                None => {
+                    if let Some(level) = Level::parse(directive) {
+                        filter.default = level;
+                    }
+                }
+            }
+        }
+        filter
+    }
+
+    /// Whether `level` is on for `target`. The longest matching target
+    /// directive wins, falling back to the overall level.
+    pub fn enabled(&self, target: &str, level: Level) -> bool {
+        let max = self
+            .targets
+            .iter()
+            .filter(|(t, _)| covers(t, target))
+            .max_by_key(|(t, _)| t.len())
+            .map_or(self.default, |(_, l)| *l);
+        max.is_some_and(|max| level <= max)
+    }
+}
+
+/// Whether a directive for `prefix` applies to `target`.
+fn covers(prefix: &str, target: &str) -> bool {
+    match target.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with("::"),
+        None => false,
+    }
+}
+
+/// The filter from `AOC_LOG`, read once.
+pub fn filter() -> &'static Filter {
+    static FILTER: OnceLock<Filter> = OnceLock::new();
+    FILTER.get_or_init(|| Filter::parse(&env::var(ENV_VAR).unwrap_or_default()))
+}
+
+/// A module path with the crate name dropped, e.g. `day3`.
+pub fn target(module_path: &str) -> &str {
+    module_path
+        .split_once("::")
+        .map_or(module_path, |(_, rest)| rest)
+}
+
+pub fn enabled(target: &str, level: Level) -> bool {
+    filter().enabled(target, level)
+}
+
+pub fn emit(target: &str, level: Level, args: fmt::Arguments) {
+    eprintln!("[{} {}] {}", level, target, args);
+}
+
+/// Whether the calling module logs at `level`, for guarding output that is
+/// expensive to build.
+///
+/// The macros are prefixed so they can't be mistaken for, or clash with,
+/// the `log` crate's.
+#[macro_export]
+macro_rules! aoc_log_enabled {
+    ($level:ident) => {
+        $crate::trace::enabled(
+            $crate::trace::target(module_path!()),
+            $crate::trace::Level::$level,
+        )
+    };
+}
+
+/// Logs `format!`-style arguments at `level` under the calling module.
+#[macro_export]
+macro_rules! aoc_log {
+    ($level:ident, $($arg:tt)+) => {{
+        let target = $crate::trace::target(module_path!());
+        if $crate::trace::enabled(target, $crate::trace::Level::$level) {
+            $crate::trace::emit(target, $crate::trace::Level::$level, format_args!($($arg)+));
+        }
+    }};
+}
+
+#[macro_export]
+macro_rules! aoc_error {
+    ($($arg:tt)+) => { $crate::aoc_log!(Error, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! aoc_warn {
+    ($($arg:tt)+) => { $crate::aoc_log!(Warn, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! aoc_info {
+    ($($arg:tt)+) => { $crate::aoc_log!(Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! aoc_debug {
+    ($($arg:tt)+) => { $crate::aoc_log!(Debug, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! aoc_trace {
+    ($($arg:tt)+) => { $crate::aoc_log!(Trace, $($arg)+) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quiet_by_default() {
+        let filter = Filter::parse("");
+        assert!(!filter.enabled("day3", Level::Error));
+    }
+
+    #[test]
+    fn per_target_levels() {
+        let filter = Filter::parse("warn, day3=trace,day4=off,day4::fixtures=debug,bogus");
+        assert!(filter.enabled("day2", Level::Warn));
+        assert!(!filter.enabled("day2", Level::Info));
+        assert!(filter.enabled("day3", Level::Trace));
+        assert!(!filter.enabled("day4", Level::Error));
+        assert!(filter.enabled("day4::fixtures", Level::Debug));
+        assert!(!filter.enabled("day30", Level::Trace));
+    }
+
+    #[test]
+    fn targets_drop_the_crate() {
+        assert_eq!(target("aoc2020::day3"), "day3");
+        assert_eq!(target("aoc2020::day4::fixtures"), "day4::fixtures");
+        assert_eq!(target("aoc2020"), "aoc2020");
+    }
+}